@@ -1,15 +1,29 @@
+use std::{convert::TryFrom, io::BufRead};
+
+mod value;
+
+pub use value::Value;
+
 #[derive(Clone, Debug)] // TODO: REMOVE Clone
 pub struct CodeParseErrorInternal {
     // TODO: rename to CodeParseErrorInternal
     pub code_pointer: Option<usize>,
-    pub message: String, // TODO: consider Cow<'static, str>
+    pub message: String,   // TODO: consider Cow<'static, str>
+    pub code: Option<u16>, // See, diagnostic::code
+    pub span: Option<Span>,
+    pub labels: Vec<(Span, String)>, // e.g. "first declared here"
+    #[allow(dead_code)] // for debugging
     pub internal_line: u32,
+    #[allow(dead_code)] // for debugging
     pub internal_file: &'static str,
 }
 
 pub struct CodeParseError {
     pub code_pointer: Option<usize>,
     pub message: String, // TODO: consider Cow<'static, str>
+    pub code: Option<u16>,
+    pub span: Option<Span>,
+    pub labels: Vec<(Span, String)>,
 }
 
 #[macro_export]
@@ -18,6 +32,9 @@ macro_rules! code_parse_error {
         CodeParseErrorInternal {
             code_pointer: Some($ptr),
             message: $msg,
+            code: None,
+            span: None,
+            labels: vec![],
             internal_line: line!(),  // TODO: add_parse_error 内で使うとline!は意味を成さなくなる
             internal_file: file!(),
         }
@@ -26,17 +43,93 @@ macro_rules! code_parse_error {
         CodeParseErrorInternal {
             code_pointer: None,
             message: $msg,
+            code: None,
+            span: None,
+            labels: vec![],
             internal_line: line!(),  // TODO: add_parse_error 内で使うとline!は意味を成さなくなる
             internal_file: file!(),
         }
     };
 }
 
+// ソースコード上の範囲。文字単位で [begin, end)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub begin: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(begin: usize, end: usize) -> Self {
+        Span { begin, end }
+    }
+}
+
 impl CodeParseErrorInternal {
+    pub fn with_code(mut self, code: u16) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push((span, message));
+        self
+    }
+
     pub fn shrink(&self) -> CodeParseError {
         CodeParseError {
             code_pointer: self.code_pointer,
             message: self.message.clone(),
+            code: self.code,
+            span: self.span,
+            labels: self.labels.clone(),
         }
     }
 }
+
+// シフト量の上限。これより大きいシフトは実行時エラーにする
+// NOTE: 多倍長整数では 1 << b が b ビットの整数を作り、コンパイル結果は b 回のループになるので、
+//       際限なく大きなシフトを許すと時間と記憶域を使い果たす。i64 の幅は十分に超える値にしている
+pub const MAX_SHIFT: i64 = 1 << 16;
+
+// 2^b. シフト演算 (a << b == a * 2^b, a >> b == floor(a / 2^b)) に使う
+pub fn pow2(b: i64) -> Option<i64> {
+    u32::try_from(b).ok().and_then(|b| 2_i64.checked_pow(b))
+}
+
+// Whitespace の readchar, readnum と同じ規則で入力を読む
+// 1 文字 (UTF-8) を読む。EOF なら -1
+pub fn read_char(input: &mut dyn BufRead) -> Result<i64, String> {
+    let mut bytes = vec![];
+    loop {
+        let buf = input.fill_buf().map_err(|e| e.to_string())?;
+        if buf.is_empty() {
+            return if bytes.is_empty() {
+                Ok(-1) // EOF
+            } else {
+                Err("invalid utf-8 input".to_owned())
+            };
+        }
+        bytes.push(buf[0]);
+        input.consume(1);
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => return Ok(s.chars().next().unwrap() as i64),
+            Err(e) if e.error_len().is_some() => return Err("invalid utf-8 input".to_owned()),
+            Err(_) => (), // incomplete
+        }
+    }
+}
+
+// 1 行を読んで整数として解釈する
+pub fn read_num(input: &mut dyn BufRead) -> Result<Value, String> {
+    let mut line = String::new();
+    if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+        return Err("unexpected end of input".to_owned());
+    }
+    Value::parse(line.trim()).ok_or_else(|| format!("invalid number: {}", line.trim()))
+}
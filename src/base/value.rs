@@ -0,0 +1,249 @@
+use std::fmt;
+
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+#[cfg(feature = "bigint")]
+use num_integer::Integer;
+#[cfg(feature = "bigint")]
+use num_traits::{ToPrimitive, Zero};
+
+#[cfg(not(feature = "bigint"))]
+use super::pow2;
+
+// プログラムが扱う整数。
+// 既定では i64。`bigint` feature を有効にすると Whitespace と同じく多倍長整数になり、
+// checked_* は (0 除算を除き) 失敗せず、wrapping_* は丸めない。
+#[cfg(not(feature = "bigint"))]
+type Inner = i64;
+#[cfg(feature = "bigint")]
+type Inner = BigInt;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Value(Inner);
+
+impl From<i64> for Value {
+    fn from(x: i64) -> Self {
+        Value(Inner::from(x))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Value {
+    // 10 進数の文字列を読む。`bigint` feature では i64 に収まらない値も読める
+    pub fn parse(s: &str) -> Option<Value> {
+        s.parse::<Inner>().ok().map(Value)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == Inner::from(0)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < Inner::from(0)
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+// Whitespace (Haskell の div, mod) に合わせて負の無限大方向に丸める
+fn div_floor(a: i64, b: i64) -> Option<i64> {
+    let q = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(q - 1)
+    } else {
+        Some(q)
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+fn mod_floor(a: i64, b: i64) -> Option<i64> {
+    let r = a.checked_rem(b)?;
+    if r != 0 && (r < 0) != (b < 0) {
+        Some(r + b)
+    } else {
+        Some(r)
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+impl Value {
+    pub fn to_i64(&self) -> Option<i64> {
+        Some(self.0)
+    }
+
+    pub fn checked_add(&self, rhs: &Value) -> Option<Value> {
+        self.0.checked_add(rhs.0).map(Value)
+    }
+
+    pub fn checked_sub(&self, rhs: &Value) -> Option<Value> {
+        self.0.checked_sub(rhs.0).map(Value)
+    }
+
+    pub fn checked_mul(&self, rhs: &Value) -> Option<Value> {
+        self.0.checked_mul(rhs.0).map(Value)
+    }
+
+    pub fn checked_neg(&self) -> Option<Value> {
+        self.0.checked_neg().map(Value)
+    }
+
+    // 0 除算と溢れは None
+    pub fn checked_div_floor(&self, rhs: &Value) -> Option<Value> {
+        div_floor(self.0, rhs.0).map(Value)
+    }
+
+    pub fn checked_mod_floor(&self, rhs: &Value) -> Option<Value> {
+        mod_floor(self.0, rhs.0).map(Value)
+    }
+
+    // a << b == a * 2^b
+    pub fn checked_shl(&self, b: u32) -> Option<Value> {
+        pow2(b as i64)
+            .and_then(|p| self.0.checked_mul(p))
+            .map(Value)
+    }
+
+    pub fn bit_and(&self, rhs: &Value) -> Value {
+        Value(self.0 & rhs.0)
+    }
+
+    pub fn bit_or(&self, rhs: &Value) -> Value {
+        Value(self.0 | rhs.0)
+    }
+
+    pub fn bit_xor(&self, rhs: &Value) -> Value {
+        Value(self.0 ^ rhs.0)
+    }
+
+    pub fn wrapping_add(&self, rhs: &Value) -> Value {
+        Value(self.0.wrapping_add(rhs.0))
+    }
+
+    pub fn wrapping_sub(&self, rhs: &Value) -> Value {
+        Value(self.0.wrapping_sub(rhs.0))
+    }
+
+    pub fn wrapping_mul(&self, rhs: &Value) -> Value {
+        Value(self.0.wrapping_mul(rhs.0))
+    }
+
+    pub fn wrapping_neg(&self) -> Value {
+        Value(self.0.wrapping_neg())
+    }
+
+    // NOTE: 0 除算以外で溢れるのは i64::MIN / -1 のみ
+    pub fn wrapping_div_floor(&self, rhs: &Value) -> Value {
+        Value(div_floor(self.0, rhs.0).unwrap_or(self.0))
+    }
+
+    pub fn wrapping_mod_floor(&self, rhs: &Value) -> Value {
+        Value(mod_floor(self.0, rhs.0).unwrap_or(0))
+    }
+
+    pub fn wrapping_shl(&self, b: u32) -> Value {
+        Value(if b < 64 { self.0.wrapping_shl(b) } else { 0 })
+    }
+
+    // 算術シフトなので floor(a / 2^b) と等しい
+    pub fn shr(&self, b: u32) -> Value {
+        Value(self.0 >> b.min(63))
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Value {
+    pub fn to_i64(&self) -> Option<i64> {
+        self.0.to_i64()
+    }
+
+    pub fn checked_add(&self, rhs: &Value) -> Option<Value> {
+        Some(self.wrapping_add(rhs))
+    }
+
+    pub fn checked_sub(&self, rhs: &Value) -> Option<Value> {
+        Some(self.wrapping_sub(rhs))
+    }
+
+    pub fn checked_mul(&self, rhs: &Value) -> Option<Value> {
+        Some(self.wrapping_mul(rhs))
+    }
+
+    pub fn checked_neg(&self) -> Option<Value> {
+        Some(self.wrapping_neg())
+    }
+
+    pub fn checked_div_floor(&self, rhs: &Value) -> Option<Value> {
+        if rhs.0.is_zero() {
+            None
+        } else {
+            Some(self.wrapping_div_floor(rhs))
+        }
+    }
+
+    pub fn checked_mod_floor(&self, rhs: &Value) -> Option<Value> {
+        if rhs.0.is_zero() {
+            None
+        } else {
+            Some(self.wrapping_mod_floor(rhs))
+        }
+    }
+
+    pub fn checked_shl(&self, b: u32) -> Option<Value> {
+        Some(self.wrapping_shl(b))
+    }
+
+    pub fn bit_and(&self, rhs: &Value) -> Value {
+        Value(&self.0 & &rhs.0)
+    }
+
+    pub fn bit_or(&self, rhs: &Value) -> Value {
+        Value(&self.0 | &rhs.0)
+    }
+
+    pub fn bit_xor(&self, rhs: &Value) -> Value {
+        Value(&self.0 ^ &rhs.0)
+    }
+
+    pub fn wrapping_add(&self, rhs: &Value) -> Value {
+        Value(&self.0 + &rhs.0)
+    }
+
+    pub fn wrapping_sub(&self, rhs: &Value) -> Value {
+        Value(&self.0 - &rhs.0)
+    }
+
+    pub fn wrapping_mul(&self, rhs: &Value) -> Value {
+        Value(&self.0 * &rhs.0)
+    }
+
+    pub fn wrapping_neg(&self) -> Value {
+        Value(-&self.0)
+    }
+
+    pub fn wrapping_div_floor(&self, rhs: &Value) -> Value {
+        Value(self.0.div_floor(&rhs.0))
+    }
+
+    pub fn wrapping_mod_floor(&self, rhs: &Value) -> Value {
+        Value(self.0.mod_floor(&rhs.0))
+    }
+
+    pub fn wrapping_shl(&self, b: u32) -> Value {
+        Value(&self.0 << b)
+    }
+
+    // BigInt の右シフトも負の無限大方向に丸める
+    pub fn shr(&self, b: u32) -> Value {
+        Value(&self.0 >> b)
+    }
+}
@@ -1,48 +1,209 @@
-use std::{io::Read, iter::repeat, process};
+use std::{
+    env, fs,
+    io::{IsTerminal, Read},
+    process,
+};
 
 use nospace20::{
-    interpret_func, parse_to_tokens, parse_to_tree, syntactic_analyze, CodeParseError, TextCode,
+    compile_to_assembly, compile_to_whitespace, interpret_func, parse_to_tokens, parse_to_tree,
+    syntactic_analyze, ArithmeticMode, CodeParseError, Diagnostic, TextCode,
 };
-use unicode_width::UnicodeWidthStr;
 
-fn handle_parse_error<T>(res: Result<T, Vec<CodeParseError>>, text: &TextCode) -> T {
-    let errors = match res {
-        Ok(x) => return x,
-        Err(e) => e,
+const USAGE: &str = "\
+usage: nospace20 [COMMAND] [OPTIONS] [FILE]
+
+commands:
+  run       interpret the program and call `main` (default)
+              --wrapping  wrap on integer overflow instead of failing
+  compile   compile the program to whitespace
+              --asm  emit whitespace assembly instead
+  tokens    print the tokens
+  ast       print the syntax tree
+  check     parse and analyze the program only
+              --format=FORMAT  print the errors as `text` (default) or `json`
+
+options:
+  --color=WHEN  color the error messages: auto (default), always or never
+
+FILE: source file. read from stdin if omitted or `-`.";
+
+enum Command {
+    Run { wrapping: bool },
+    Compile { asm: bool },
+    Tokens,
+    Ast,
+    Check { json: bool },
+}
+
+struct Options {
+    command: Command,
+    path: Option<String>,
+    color: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut iter = args.iter().peekable();
+    let command = match iter.peek().map(|s| s.as_str()) {
+        Some("run") => Some(Command::Run { wrapping: false }),
+        Some("compile") => Some(Command::Compile { asm: false }),
+        Some("tokens") => Some(Command::Tokens),
+        Some("ast") => Some(Command::Ast),
+        Some("check") => Some(Command::Check { json: false }),
+        _ => None,
     };
+    if command.is_some() {
+        iter.next();
+    }
+    // the command can be omitted
+    let mut command = command.unwrap_or(Command::Run { wrapping: false });
+    let mut path = None;
+    let mut color = std::io::stdout().is_terminal();
+    for arg in iter {
+        match (&mut command, arg.as_str()) {
+            (_, "--color=auto") => color = std::io::stdout().is_terminal(),
+            (_, "--color=always") => color = true,
+            (_, "--color=never") => color = false,
+            (Command::Compile { asm }, "--asm") => *asm = true,
+            (Command::Run { wrapping }, "--wrapping") => *wrapping = true,
+            (Command::Check { json }, "--format=text") => *json = false,
+            (Command::Check { json }, "--format=json") => *json = true,
+            (_, "-h") | (_, "--help") => return Err(String::new()),
+            (_, x) if x != "-" && x.starts_with('-') => {
+                return Err(format!("unknown option: {}", x))
+            }
+            (_, x) => {
+                if path.is_some() {
+                    return Err(format!("unexpected argument: {}", x));
+                }
+                path = Some(x.to_owned());
+            }
+        }
+    }
+    Ok(Options {
+        command,
+        path,
+        color,
+    })
+}
+
+fn read_source(path: &Option<String>) -> String {
+    match path.as_deref() {
+        None | Some("-") => {
+            let mut code_raw = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut code_raw) {
+                eprintln!("error: failed to read stdin: {}", e);
+                process::exit(1);
+            }
+            code_raw
+        }
+        Some(path) => match fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("error: failed to read {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+    }
+}
 
-    for error in errors.iter().take(3) {
-        println!("error: {}", error.message);
-        if let Some(code_pointer) = error.code_pointer {
-            let (line_no, column) = text.char_index_to_line(code_pointer);
-            let line_str = text.line(line_no);
-            println!("line:{} column:{}", line_no, column);
-            println!("{}", line_str);
+// エラーの出力先と形式
+struct Reporter<'a> {
+    text: TextCode<'a>,
+    path: Option<&'a str>, // None: stdin
+    color: bool,
+    json: bool,
+}
+
+impl<'a> Reporter<'a> {
+    // json なら、エラーが無くても空の配列を出力する
+    fn report(&self, diagnostics: &[Diagnostic]) {
+        if self.json {
+            let values: Vec<_> = diagnostics
+                .iter()
+                .map(|d| d.to_json(&self.text, self.path))
+                .collect();
+            println!("{}", serde_json::Value::Array(values));
+            return;
+        }
+        for d in diagnostics.iter() {
+            println!("{}", d.render(&self.text, self.color));
+        }
+        if !diagnostics.is_empty() {
             println!(
-                "{}^",
-                repeat(' ')
-                    .take(UnicodeWidthStr::width(
-                        line_str.chars().take(column).collect::<String>().as_str()
-                    ))
-                    .collect::<String>()
+                "aborting due to {} previous error{}",
+                diagnostics.len(),
+                if diagnostics.len() == 1 { "" } else { "s" }
             );
         }
     }
+}
+
+fn handle_parse_error<T>(res: Result<T, Vec<CodeParseError>>, reporter: &Reporter) -> T {
+    let errors = match res {
+        Ok(x) => return x,
+        Err(e) => e,
+    };
+
+    let diagnostics: Vec<_> = errors.iter().map(Diagnostic::from).collect();
+    reporter.report(&diagnostics);
 
     process::exit(1);
 }
 
 fn main() {
-    let mut code_raw = String::new();
-    std::io::stdin().read_to_string(&mut code_raw).ok();
-    let text = TextCode::new(&code_raw);
-    let t = handle_parse_error(parse_to_tokens(&code_raw), &text);
-    let s = handle_parse_error(parse_to_tree(&t), &text);
-    let a = syntactic_analyze(&s);
-    let result = interpret_func(&a, "main");
-    if let Some(val) = result {
-        println!("main returns: {}", val);
-    } else {
-        println!("main exited");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(x) => x,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}", message);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let code_raw = read_source(&options.path);
+    let reporter = Reporter {
+        text: TextCode::new(&code_raw),
+        path: options.path.as_deref().filter(|p| *p != "-"),
+        color: options.color,
+        json: matches!(options.command, Command::Check { json: true }),
+    };
+    let text = &reporter.text;
+    let t = handle_parse_error(parse_to_tokens(&code_raw), &reporter);
+    if let Command::Tokens = options.command {
+        for (token, info) in t.iter() {
+            let (line_no, column) = text.char_index_to_line(info.code_pointer);
+            println!("{}:{}\t{:?}", line_no, column, token);
+        }
+        return;
+    }
+    let s = handle_parse_error(parse_to_tree(&t), &reporter);
+    if let Command::Ast = options.command {
+        println!("{:#?}", s);
+        return;
+    }
+    let a = handle_parse_error(syntactic_analyze(&s), &reporter);
+    match options.command {
+        Command::Run { wrapping } => {
+            let arithmetic = if wrapping {
+                ArithmeticMode::Wrapping
+            } else {
+                ArithmeticMode::Checked
+            };
+            match interpret_func(&a, "main", arithmetic) {
+                Ok(Some(val)) => println!("main returns: {}", val),
+                Ok(None) => println!("main exited"),
+                Err(e) => {
+                    reporter.report(&[Diagnostic::from(&e)]);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Compile { asm: false } => print!("{}", compile_to_whitespace(&a)),
+        Command::Compile { asm: true } => print!("{}", compile_to_assembly(&a)),
+        Command::Check { .. } => reporter.report(&[]),
+        Command::Tokens | Command::Ast => unreachable!(),
     }
 }
@@ -0,0 +1,167 @@
+use crate::{base::CodeParseErrorInternal, code_parse_error, whitespace::Instruction};
+
+// human-readable whitespace assembly.
+//
+//   label main
+//     push 3
+//     call f
+//     jz L1   # comment
+//
+// 1 行に 1 命令。`#` 以降はコメント。
+
+fn mnemonic(inst: &Instruction) -> &'static str {
+    match inst {
+        Instruction::Push(_) => "push",
+        Instruction::Dup => "dup",
+        Instruction::Copy(_) => "copy",
+        Instruction::Swap => "swap",
+        Instruction::Discard => "discard",
+        Instruction::Slide(_) => "slide",
+        Instruction::Add => "add",
+        Instruction::Sub => "sub",
+        Instruction::Mul => "mul",
+        Instruction::Div => "div",
+        Instruction::Mod => "mod",
+        Instruction::Store => "store",
+        Instruction::Retrieve => "retrieve",
+        Instruction::Mark(_) => "label",
+        Instruction::Call(_) => "call",
+        Instruction::Jump(_) => "jump",
+        Instruction::JumpZero(_) => "jz",
+        Instruction::JumpNegative(_) => "jn",
+        Instruction::Return => "ret",
+        Instruction::Exit => "end",
+        Instruction::OutChar => "outchar",
+        Instruction::OutNum => "outnum",
+        Instruction::ReadChar => "readchar",
+        Instruction::ReadNum => "readnum",
+    }
+}
+
+pub fn emit(instructions: &[Instruction]) -> String {
+    let mut text = String::new();
+    for inst in instructions {
+        if !matches!(inst, Instruction::Mark(_)) {
+            text.push_str("  ");
+        }
+        text.push_str(mnemonic(inst));
+        match inst {
+            Instruction::Push(n) | Instruction::Copy(n) | Instruction::Slide(n) => {
+                text.push_str(&format!(" {}", n))
+            }
+            Instruction::Mark(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => text.push_str(&format!(" {}", label)),
+            _ => (),
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn parse_line(words: &[(usize, &str)]) -> Result<Instruction, CodeParseErrorInternal> {
+    let (ptr, name) = words[0];
+    let number = || match words.get(1) {
+        Some((p, w)) => w
+            .parse::<i64>()
+            .map_err(|_| code_parse_error!(*p, format!("invalid number: {}", w))),
+        None => Err(code_parse_error!(
+            ptr,
+            format!("`{}` requires a number", name)
+        )),
+    };
+    let label = || match words.get(1) {
+        Some((_, w)) => Ok(w.to_string()),
+        None => Err(code_parse_error!(
+            ptr,
+            format!("`{}` requires a label", name)
+        )),
+    };
+    let inst = match name {
+        "push" => Instruction::Push(number()?),
+        "dup" => Instruction::Dup,
+        "copy" => Instruction::Copy(number()?),
+        "swap" => Instruction::Swap,
+        "discard" => Instruction::Discard,
+        "slide" => Instruction::Slide(number()?),
+        "add" => Instruction::Add,
+        "sub" => Instruction::Sub,
+        "mul" => Instruction::Mul,
+        "div" => Instruction::Div,
+        "mod" => Instruction::Mod,
+        "store" => Instruction::Store,
+        "retrieve" => Instruction::Retrieve,
+        "label" => Instruction::Mark(label()?),
+        "call" => Instruction::Call(label()?),
+        "jump" => Instruction::Jump(label()?),
+        "jz" => Instruction::JumpZero(label()?),
+        "jn" => Instruction::JumpNegative(label()?),
+        "ret" => Instruction::Return,
+        "end" => Instruction::Exit,
+        "outchar" => Instruction::OutChar,
+        "outnum" => Instruction::OutNum,
+        "readchar" => Instruction::ReadChar,
+        "readnum" => Instruction::ReadNum,
+        _ => {
+            return Err(code_parse_error!(
+                ptr,
+                format!("unknown instruction: {}", name)
+            ))
+        }
+    };
+    let expected_len = match inst {
+        Instruction::Push(_)
+        | Instruction::Copy(_)
+        | Instruction::Slide(_)
+        | Instruction::Mark(_)
+        | Instruction::Call(_)
+        | Instruction::Jump(_)
+        | Instruction::JumpZero(_)
+        | Instruction::JumpNegative(_) => 2,
+        _ => 1,
+    };
+    if let Some((p, _)) = words.get(expected_len) {
+        return Err(code_parse_error!(*p, "too many operands".to_owned()));
+    }
+    Ok(inst)
+}
+
+pub fn parse(text: &str) -> Result<Vec<Instruction>, Vec<CodeParseErrorInternal>> {
+    let mut instructions = vec![];
+    let mut errors = vec![];
+    let mut line_pointer = 0;
+    for line in text.split('\n') {
+        // split into words with their char index
+        let mut words = Vec::<(usize, &str)>::new();
+        let mut begin = None;
+        for (i, (byte_idx, c)) in line.char_indices().enumerate() {
+            if c == '#' || c.is_whitespace() {
+                if let Some((b, bi)) = begin.take() {
+                    words.push((line_pointer + b, &line[bi..byte_idx]));
+                }
+                if c == '#' {
+                    break;
+                }
+            } else if begin.is_none() {
+                begin = Some((i, byte_idx));
+            }
+        }
+        if let Some((b, bi)) = begin {
+            words.push((line_pointer + b, &line[bi..]));
+        }
+        if !words.is_empty() {
+            match parse_line(&words) {
+                Ok(inst) => instructions.push(inst),
+                Err(e) => errors.push(e),
+            }
+        }
+        line_pointer += line.chars().count() + 1;
+    }
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
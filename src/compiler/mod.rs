@@ -1 +1,861 @@
-// todo!
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    base::{pow2, Value, MAX_SHIFT},
+    syntactic_analyzer::{ExecExpression, ExecStatement, Function, Scope},
+    tree_parser::{Operator1, Operator2},
+    whitespace::{Instruction, Label},
+};
+
+pub mod asm;
+
+// convert the analyzed tree to whitespace instructions.
+//
+// heap layout:
+//   [0]: frame pointer
+//   [1]: stack pointer (the next free address)
+//   [2..]: global variables, then frames
+// frame layout:
+//   [fp + 0]: frame pointer of the caller
+//   [fp + 1..]: arguments and local variables (an array occupies its size)
+// 関数の戻り値は必ずスタックに 1 つ積まれる。
+// 名前の解決と代入の左辺は syntactic_analyzer で検査済みなので、ここでは誤りを報告しない。
+
+const HEAP_FRAME_POINTER: i64 = 0;
+const HEAP_STACK_POINTER: i64 = 1;
+const HEAP_BEGIN: i64 = 2;
+
+// 大域変数の初期化式をまとめた関数。識別子と衝突しない名前にする
+const LABEL_GLOBAL_INITIALIZER: &str = "__global.init";
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Runtime {
+    AssertFailed,
+    AssertNotFailed,
+    IndexOutOfRange,
+    InvalidShift,
+    InvalidTraceKey,
+    // 以下はサブルーチン
+    Pow2,       // [b] -> [2^b]
+    ShiftRight, // [a b] -> [a >> b]
+    BitAnd,     // [a b] -> [a & b]
+    BitOr,
+    BitXor,
+}
+
+impl Runtime {
+    fn label(&self) -> Label {
+        match self {
+            Runtime::AssertFailed => "__rt.assert_failed".to_owned(),
+            Runtime::AssertNotFailed => "__rt.assert_not_failed".to_owned(),
+            Runtime::IndexOutOfRange => "__rt.index_out_of_range".to_owned(),
+            Runtime::InvalidShift => "__rt.invalid_shift".to_owned(),
+            Runtime::InvalidTraceKey => "__rt.invalid_trace_key".to_owned(),
+            Runtime::Pow2 => "__rt.pow2".to_owned(),
+            Runtime::ShiftRight => "__rt.shift_right".to_owned(),
+            Runtime::BitAnd => "__rt.bit_and".to_owned(),
+            Runtime::BitOr => "__rt.bit_or".to_owned(),
+            Runtime::BitXor => "__rt.bit_xor".to_owned(),
+        }
+    }
+}
+
+struct LoopLabels {
+    label_continue: Label,
+    label_break: Label,
+    stack_depth: usize,
+}
+
+struct Compiler {
+    code: Vec<Instruction>,
+    label_count: usize,
+    runtime: BTreeSet<Runtime>,
+    globals: BTreeMap<String, (i64, i64)>, // name -> (address, size)
+    // 現在コンパイル中の関数の情報
+    variables: BTreeMap<String, (i64, i64)>, // name -> (offset in the frame, size)
+    stack_depth: usize,                      // 式の評価途中でスタックに残っている値の数
+    loops: Vec<LoopLabels>,
+}
+
+impl Compiler {
+    fn new() -> Self {
+        Self {
+            code: vec![],
+            label_count: 0,
+            runtime: BTreeSet::new(),
+            globals: BTreeMap::new(),
+            variables: BTreeMap::new(),
+            stack_depth: 0,
+            loops: vec![],
+        }
+    }
+
+    fn emit(&mut self, inst: Instruction) {
+        self.code.push(inst);
+    }
+
+    fn new_label_id(&mut self) -> usize {
+        let i = self.label_count;
+        self.label_count += 1;
+        i
+    }
+
+    fn emit_print_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.emit(Instruction::Push(c as i64));
+            self.emit(Instruction::OutChar);
+        }
+    }
+
+    // push the address of the local variable
+    fn emit_variable_address(&mut self, name: &str) {
+        let offset = match self.variables.get(name) {
+            Some((x, _)) => *x,
+            None => unreachable!("internal error: unknown variable name `{}`", name),
+        };
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Push(offset));
+        self.emit(Instruction::Add);
+    }
+
+    fn emit_global_address(&mut self, name: &str) {
+        let address = match self.globals.get(name) {
+            Some((x, _)) => *x,
+            None => unreachable!("internal error: unknown global variable name `{}`", name),
+        };
+        self.emit(Instruction::Push(address));
+    }
+
+    // [index] -> [address of the element]
+    fn emit_array_element_address(&mut self, name: &str, is_global: bool) {
+        let variables = if is_global {
+            &self.globals
+        } else {
+            &self.variables
+        };
+        let size = match variables.get(name) {
+            Some((_, size)) => *size,
+            None => unreachable!("internal error: unknown array name `{}`", name),
+        };
+        // 0 <= index < size
+        let label_ok = format!("index.{}.ok", self.new_label_id());
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::JumpNegative(Runtime::IndexOutOfRange.label()));
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::Push(size));
+        self.emit(Instruction::Sub);
+        self.emit(Instruction::JumpNegative(label_ok.clone()));
+        self.emit(Instruction::Jump(Runtime::IndexOutOfRange.label()));
+        self.emit(Instruction::Mark(label_ok));
+        self.runtime.insert(Runtime::IndexOutOfRange);
+        if is_global {
+            self.emit_global_address(name);
+        } else {
+            self.emit_variable_address(name);
+        }
+        self.emit(Instruction::Add);
+    }
+
+    // fill the local array with 0
+    fn emit_initialize_array(&mut self, name: &str) {
+        let size = match self.variables.get(name) {
+            Some((_, size)) => *size,
+            None => unreachable!("internal error: unknown array name `{}`", name),
+        };
+        let id = self.new_label_id();
+        let label_loop = format!("clear.{}.loop", id);
+        let label_end = format!("clear.{}.end", id);
+        // [i] (i = size, ..., 1)
+        self.emit(Instruction::Push(size));
+        self.emit(Instruction::Mark(label_loop.clone()));
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::JumpZero(label_end.clone()));
+        self.emit(Instruction::Push(1));
+        self.emit(Instruction::Sub);
+        // [base + i - 1] = 0
+        self.emit(Instruction::Dup);
+        self.emit_variable_address(name);
+        self.emit(Instruction::Add);
+        self.emit(Instruction::Push(0));
+        self.emit(Instruction::Store);
+        self.emit(Instruction::Jump(label_loop));
+        self.emit(Instruction::Mark(label_end));
+        self.emit(Instruction::Discard);
+    }
+
+    // [value] -> [] and return to the caller
+    fn emit_return(&mut self) {
+        if self.stack_depth > 0 {
+            self.emit(Instruction::Slide(self.stack_depth as i64));
+        }
+        // stack pointer = frame pointer
+        self.emit(Instruction::Push(HEAP_STACK_POINTER));
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Store);
+        // frame pointer = [frame pointer]
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Store);
+        self.emit(Instruction::Return);
+    }
+
+    // [a b] -> [1 if `jump` is taken on (a - b) else 0] (inverted if `inverse`)
+    fn emit_condition(&mut self, jump: fn(Label) -> Instruction, inverse: bool) {
+        let id = self.new_label_id();
+        let label_true = format!("cmp.{}.true", id);
+        let label_end = format!("cmp.{}.end", id);
+        self.emit(Instruction::Sub);
+        self.emit(jump(label_true.clone()));
+        self.emit(Instruction::Push(if inverse { 1 } else { 0 }));
+        self.emit(Instruction::Jump(label_end.clone()));
+        self.emit(Instruction::Mark(label_true));
+        self.emit(Instruction::Push(if inverse { 0 } else { 1 }));
+        self.emit(Instruction::Mark(label_end));
+    }
+
+    fn emit_call_runtime(&mut self, runtime: Runtime) {
+        self.emit(Instruction::Call(runtime.label()));
+        if let Runtime::Pow2 | Runtime::ShiftRight = runtime {
+            self.runtime.insert(Runtime::InvalidShift);
+        }
+        self.runtime.insert(runtime);
+    }
+
+    fn compile_call_function(&mut self, id: &str, args: &[ExecExpression]) {
+        match id {
+            "__clog" => {
+                self.compile_expression(args.first().unwrap());
+                self.emit_print_str("__clog: ");
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::OutNum);
+                self.emit_print_str("\n");
+            }
+            "__assert" => {
+                self.compile_expression(args.first().unwrap());
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::JumpZero(Runtime::AssertFailed.label()));
+                self.runtime.insert(Runtime::AssertFailed);
+            }
+            "__assert_not" => {
+                let label_ok = format!("assert.{}.ok", self.new_label_id());
+                self.compile_expression(args.first().unwrap());
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::JumpZero(label_ok.clone()));
+                self.emit(Instruction::Jump(Runtime::AssertNotFailed.label()));
+                self.emit(Instruction::Mark(label_ok));
+                self.runtime.insert(Runtime::AssertNotFailed);
+            }
+            "__trace" => {
+                // NOTE: 負のアドレスに記録する。負の key は他のアドレスと重なるので弾く
+                // [-1 - key] += 1
+                self.compile_expression(args.first().unwrap());
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::JumpNegative(Runtime::InvalidTraceKey.label()));
+                self.runtime.insert(Runtime::InvalidTraceKey);
+                self.emit(Instruction::Push(-1));
+                self.emit(Instruction::Swap);
+                self.emit(Instruction::Sub);
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Retrieve);
+                self.emit(Instruction::Push(1));
+                self.emit(Instruction::Add);
+                self.emit(Instruction::Store);
+                self.emit(Instruction::Discard);
+                self.emit(Instruction::Push(0));
+            }
+            "__put_int" | "__put_char" => {
+                self.compile_expression(args.first().unwrap());
+                if id == "__put_int" {
+                    self.emit(Instruction::OutNum);
+                } else {
+                    self.emit(Instruction::OutChar);
+                }
+                self.emit(Instruction::Push(0));
+            }
+            "__puts" => {
+                // NOTE: 引数は文字のコードポイントに展開されている
+                for a in args {
+                    self.compile_expression(a);
+                    self.emit(Instruction::OutChar);
+                }
+                self.emit(Instruction::Push(0));
+            }
+            "__read_int" | "__read_char" => {
+                // NOTE: スタックポインタの指す空き領域に読み込む
+                self.emit(Instruction::Push(HEAP_STACK_POINTER));
+                self.emit(Instruction::Retrieve);
+                self.emit(Instruction::Dup);
+                if id == "__read_int" {
+                    self.emit(Instruction::ReadNum);
+                } else {
+                    self.emit(Instruction::ReadChar);
+                }
+                self.emit(Instruction::Retrieve);
+            }
+            _ => {
+                for a in args {
+                    self.compile_expression(a);
+                    self.stack_depth += 1;
+                }
+                self.stack_depth -= args.len();
+                self.emit(Instruction::Call(id.to_owned()));
+            }
+        }
+    }
+
+    fn compile_while(&mut self, cond: &ExecExpression, code: &[ExecStatement]) {
+        let id = self.new_label_id();
+        let label_cond = format!("while.{}.cond", id);
+        let label_end = format!("while.{}.end", id);
+        self.emit(Instruction::Mark(label_cond.clone()));
+        self.compile_expression(cond);
+        self.emit(Instruction::JumpZero(label_end.clone()));
+        self.loops.push(LoopLabels {
+            label_continue: label_cond.clone(),
+            label_break: label_end.clone(),
+            stack_depth: self.stack_depth,
+        });
+        self.compile_statements(code);
+        self.loops.pop();
+        self.emit(Instruction::Jump(label_cond));
+        self.emit(Instruction::Mark(label_end));
+        self.emit(Instruction::Push(0));
+    }
+
+    fn compile_if(
+        &mut self,
+        branches: &[(Box<ExecExpression>, Vec<ExecStatement>)],
+        stats_else: &[ExecStatement],
+    ) {
+        // NOTE: interpreter と同様、cond が 0 の分岐を実行する
+        // 条件を順に評価して各分岐へ飛ぶ。ブロックは入れ子にせず並べる
+        //   cond0; jz if.N.0; cond1; jz if.N.1; else; jmp end;
+        //   if.N.0: block0; jmp end; if.N.1: block1; end:
+        let id = self.new_label_id();
+        let label_branch = |i: usize| format!("if.{}.{}", id, i);
+        let label_end = format!("if.{}.end", id);
+        for (i, (cond, _)) in branches.iter().enumerate() {
+            self.compile_expression(cond);
+            self.emit(Instruction::JumpZero(label_branch(i)));
+        }
+        self.compile_statements(stats_else);
+        for (i, (_, stats)) in branches.iter().enumerate() {
+            self.emit(Instruction::Jump(label_end.clone()));
+            self.emit(Instruction::Mark(label_branch(i)));
+            self.compile_statements(stats);
+        }
+        self.emit(Instruction::Mark(label_end));
+        self.emit(Instruction::Push(0));
+    }
+
+    fn compile_operation1(&mut self, op: &Operator1, expr1: &ExecExpression) {
+        self.compile_expression(expr1);
+        match op {
+            Operator1::Negative => {
+                self.emit(Instruction::Push(-1));
+                self.emit(Instruction::Mul);
+            }
+            Operator1::Not => {
+                // !a <=> a == 0
+                self.emit(Instruction::Push(0));
+                self.emit_condition(Instruction::JumpZero, false);
+            }
+        }
+    }
+
+    // 短絡評価。結果は 0 か 1
+    fn compile_logical_operation(
+        &mut self,
+        op: &Operator2,
+        expr1: &ExecExpression,
+        expr2: &ExecExpression,
+    ) {
+        let id = self.new_label_id();
+        let label_rhs = format!("logical.{}.rhs", id);
+        let label_false = format!("logical.{}.false", id);
+        let label_end = format!("logical.{}.end", id);
+        self.compile_expression(expr1);
+        if let Operator2::LogicalAnd = op {
+            self.emit(Instruction::JumpZero(label_false.clone()));
+        } else {
+            self.emit(Instruction::JumpZero(label_rhs.clone()));
+            self.emit(Instruction::Push(1));
+            self.emit(Instruction::Jump(label_end.clone()));
+            self.emit(Instruction::Mark(label_rhs));
+        }
+        self.compile_expression(expr2);
+        self.emit(Instruction::JumpZero(label_false.clone()));
+        self.emit(Instruction::Push(1));
+        self.emit(Instruction::Jump(label_end.clone()));
+        self.emit(Instruction::Mark(label_false));
+        self.emit(Instruction::Push(0));
+        self.emit(Instruction::Mark(label_end));
+    }
+
+    fn compile_operation2(
+        &mut self,
+        op: &Operator2,
+        expr1: &ExecExpression,
+        expr2: &ExecExpression,
+    ) {
+        if let (
+            Operator2::Assign,
+            ExecExpression::Variable(_, _) | ExecExpression::GlobalVariable(_, _),
+        ) = (op, expr1)
+        {
+            self.compile_expression(expr2);
+            self.emit(Instruction::Dup);
+            match expr1 {
+                ExecExpression::Variable(name, _) => self.emit_variable_address(name),
+                ExecExpression::GlobalVariable(name, _) => self.emit_global_address(name),
+                _ => unreachable!("internal error: left value is not a variable"),
+            }
+            self.emit(Instruction::Swap);
+            self.emit(Instruction::Store);
+            return;
+        }
+        if op.is_assign() {
+            // NOTE: 左辺のアドレスを先に評価する
+            match expr1 {
+                ExecExpression::Variable(name, _) => self.emit_variable_address(name),
+                ExecExpression::GlobalVariable(name, _) => self.emit_global_address(name),
+                ExecExpression::ArrayElement(name, _, index, _)
+                | ExecExpression::GlobalArrayElement(name, _, index, _) => {
+                    let is_global = matches!(expr1, ExecExpression::GlobalArrayElement(..));
+                    self.compile_expression(index);
+                    self.emit_array_element_address(name, is_global);
+                }
+                _ => unreachable!("internal error: left value is not a variable"),
+            }
+            let compound = op.compound_operator();
+            // [address] -> [address old]
+            let depth = if compound.is_some() {
+                self.emit(Instruction::Dup);
+                self.emit(Instruction::Retrieve);
+                2
+            } else {
+                1
+            };
+            self.stack_depth += depth;
+            self.compile_expression(expr2);
+            self.stack_depth -= depth;
+            if let Some(compound) = compound {
+                self.emit_operation2(&compound);
+            }
+            // [address value] -> [value]
+            self.emit(Instruction::Swap);
+            self.emit(Instruction::Copy(1));
+            self.emit(Instruction::Store);
+            return;
+        }
+        if let Operator2::LogicalAnd | Operator2::LogicalOr = op {
+            return self.compile_logical_operation(op, expr1, expr2);
+        }
+        if let (Operator2::ShiftLeft | Operator2::ShiftRight, ExecExpression::Factor(n, _)) =
+            (op, expr2)
+        {
+            // 定数のシフトは乗除算にする
+            if let Some(p) = n.to_i64().and_then(pow2) {
+                self.compile_expression(expr1);
+                self.emit(Instruction::Push(p));
+                if let Operator2::ShiftLeft = op {
+                    self.emit(Instruction::Mul);
+                } else {
+                    self.emit(Instruction::Div);
+                }
+                return;
+            }
+        }
+        self.compile_expression(expr1);
+        self.stack_depth += 1;
+        self.compile_expression(expr2);
+        self.stack_depth -= 1;
+        self.emit_operation2(op);
+    }
+
+    // [a b] -> [a op b]
+    fn emit_operation2(&mut self, op: &Operator2) {
+        match op {
+            Operator2::Plus => self.emit(Instruction::Add),
+            Operator2::Minus => self.emit(Instruction::Sub),
+            Operator2::Multiply => self.emit(Instruction::Mul),
+            Operator2::Divide => self.emit(Instruction::Div),
+            Operator2::Modulo => self.emit(Instruction::Mod),
+            Operator2::BitAnd => self.emit_call_runtime(Runtime::BitAnd),
+            Operator2::BitOr => self.emit_call_runtime(Runtime::BitOr),
+            Operator2::BitXor => self.emit_call_runtime(Runtime::BitXor),
+            Operator2::ShiftLeft => {
+                self.emit_call_runtime(Runtime::Pow2);
+                self.emit(Instruction::Mul);
+            }
+            Operator2::ShiftRight => self.emit_call_runtime(Runtime::ShiftRight),
+            Operator2::Equal => self.emit_condition(Instruction::JumpZero, false),
+            Operator2::NotEqual => self.emit_condition(Instruction::JumpZero, true),
+            Operator2::Less => self.emit_condition(Instruction::JumpNegative, false),
+            Operator2::LessEqual => {
+                // a <= b <=> b < a is false
+                self.emit(Instruction::Swap);
+                self.emit_condition(Instruction::JumpNegative, true);
+            }
+            Operator2::Greater => {
+                self.emit(Instruction::Swap);
+                self.emit_condition(Instruction::JumpNegative, false);
+            }
+            Operator2::GreaterEqual => self.emit_condition(Instruction::JumpNegative, true),
+            // 代入と短絡評価は compile_operation2 で処理する
+            _ => unreachable!(),
+        }
+    }
+
+    // 値を 1 つスタックに積む
+    // push 命令の引数は i64 なので、収まらない定数は 2^62 進数で組み立てる
+    fn emit_push_value(&mut self, v: &Value) {
+        if let Some(n) = v.to_i64() {
+            self.emit(Instruction::Push(n));
+            return;
+        }
+        let base = Value::from(1 << 62);
+        self.emit_push_value(&v.checked_div_floor(&base).unwrap());
+        self.emit(Instruction::Push(1 << 62));
+        self.emit(Instruction::Mul);
+        self.emit_push_value(&v.checked_mod_floor(&base).unwrap());
+        self.emit(Instruction::Add);
+    }
+
+    fn compile_expression(&mut self, expr: &ExecExpression) {
+        match expr {
+            ExecExpression::Operation1(op, expr1, _, _) => self.compile_operation1(op, expr1),
+            ExecExpression::Operation2(op, expr1, expr2, _, _) => {
+                self.compile_operation2(op, expr1, expr2)
+            }
+            ExecExpression::Function(id, args, _) => self.compile_call_function(id, args),
+            ExecExpression::Factor(v, _) => self.emit_push_value(v),
+            ExecExpression::Variable(name, _) => {
+                self.emit_variable_address(name);
+                self.emit(Instruction::Retrieve);
+            }
+            ExecExpression::GlobalVariable(name, _) => {
+                self.emit_global_address(name);
+                self.emit(Instruction::Retrieve);
+            }
+            ExecExpression::ArrayElement(name, _, index, _) => {
+                self.compile_expression(index);
+                self.emit_array_element_address(name, false);
+                self.emit(Instruction::Retrieve);
+            }
+            ExecExpression::GlobalArrayElement(name, _, index, _) => {
+                self.compile_expression(index);
+                self.emit_array_element_address(name, true);
+                self.emit(Instruction::Retrieve);
+            }
+            ExecExpression::If(branches, stats_else, _) => self.compile_if(branches, stats_else),
+            ExecExpression::While(cond, code, _) => self.compile_while(cond, code),
+        }
+    }
+
+    fn compile_jump_loop(&mut self, is_break: bool) {
+        let lp = self.loops.last().expect("syntax error: no loop to escape");
+        let depth = lp.stack_depth;
+        let label = if is_break {
+            lp.label_break.clone()
+        } else {
+            lp.label_continue.clone()
+        };
+        for _ in depth..self.stack_depth {
+            self.emit(Instruction::Discard);
+        }
+        self.emit(Instruction::Jump(label));
+    }
+
+    fn compile_statement(&mut self, statement: &ExecStatement) {
+        match statement {
+            ExecStatement::Expression(expr, _) => {
+                self.compile_expression(expr);
+                self.emit(Instruction::Discard);
+            }
+            ExecStatement::Return(expr, _) => {
+                self.compile_expression(expr);
+                self.emit_return();
+            }
+            ExecStatement::Break(_) => self.compile_jump_loop(true),
+            ExecStatement::Continue(_) => self.compile_jump_loop(false),
+            ExecStatement::InitializeArray(name, _) => self.emit_initialize_array(name),
+        }
+    }
+
+    fn compile_statements(&mut self, statements: &[ExecStatement]) {
+        for statement in statements {
+            self.compile_statement(statement);
+        }
+    }
+
+    fn compile_function(&mut self, name: &str, func: &Function) {
+        self.variables.clear();
+        let mut frame_size = 1;
+        for a in func.args.iter() {
+            self.variables.insert(a.clone(), (frame_size, 1));
+            frame_size += 1;
+        }
+        for v in func.scope.variables.iter() {
+            if !self.variables.contains_key(&v.identifier) {
+                let size = v.array_size.unwrap_or(1);
+                self.variables
+                    .insert(v.identifier.clone(), (frame_size, size));
+                frame_size += size;
+            }
+        }
+
+        self.emit(Instruction::Mark(name.to_owned()));
+        // [new frame pointer] = frame pointer
+        // frame pointer = stack pointer
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Push(HEAP_STACK_POINTER));
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Store);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Store);
+        // stack pointer += frame size
+        self.emit(Instruction::Push(HEAP_STACK_POINTER));
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Retrieve);
+        self.emit(Instruction::Push(frame_size));
+        self.emit(Instruction::Add);
+        self.emit(Instruction::Store);
+        // arguments are pushed in order, so pop them in reverse order
+        for a in func.args.iter().rev() {
+            self.emit_variable_address(a);
+            self.emit(Instruction::Swap);
+            self.emit(Instruction::Store);
+        }
+        // NOTE: ヒープは以前の呼び出しの値が残っているので初期化が必要
+        // 配列は InitializeArray で初期化する
+        for v in func.scope.variables.iter() {
+            if func.args.contains(&v.identifier) || v.array_size.is_some() {
+                continue;
+            }
+            self.emit_variable_address(&v.identifier);
+            self.emit(Instruction::Push(0));
+            self.emit(Instruction::Store);
+        }
+
+        self.stack_depth = 0;
+        self.compile_statements(&func.code);
+        self.emit(Instruction::Push(0));
+        self.emit_return();
+    }
+
+    fn compile_runtime(&mut self, runtime: &Runtime) {
+        self.emit(Instruction::Mark(runtime.label()));
+        match runtime {
+            Runtime::AssertFailed => self.emit_print_str("assertion failed: value == 0\n"),
+            Runtime::AssertNotFailed => self.emit_print_str("assertion failed: value != 0\n"),
+            Runtime::IndexOutOfRange => self.emit_print_str("index out of range\n"),
+            Runtime::InvalidShift => self.emit_print_str("invalid shift amount\n"),
+            Runtime::InvalidTraceKey => self.emit_print_str("invalid trace key\n"),
+            Runtime::Pow2 => return self.compile_runtime_pow2(),
+            Runtime::ShiftRight => return self.compile_runtime_shift_right(),
+            Runtime::BitAnd | Runtime::BitOr | Runtime::BitXor => {
+                return self.compile_runtime_bit_operation(runtime)
+            }
+        }
+        self.emit(Instruction::Exit);
+    }
+
+    // [b] -> [b]. シフト量が 0 以上 MAX_SHIFT 以下でなければ停止する
+    fn emit_check_shift(&mut self) {
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::JumpNegative(Runtime::InvalidShift.label()));
+        self.emit(Instruction::Push(MAX_SHIFT));
+        self.emit(Instruction::Copy(1));
+        self.emit(Instruction::Sub);
+        self.emit(Instruction::JumpNegative(Runtime::InvalidShift.label()));
+    }
+
+    // [b] -> [2^b]
+    fn compile_runtime_pow2(&mut self) {
+        let label_loop = format!("{}.loop", Runtime::Pow2.label());
+        let label_end = format!("{}.end", Runtime::Pow2.label());
+        self.emit_check_shift();
+        // [r b]
+        self.emit(Instruction::Push(1));
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Mark(label_loop.clone()));
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::JumpZero(label_end.clone()));
+        self.emit(Instruction::Push(1));
+        self.emit(Instruction::Sub);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Push(2));
+        self.emit(Instruction::Mul);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Jump(label_loop));
+        self.emit(Instruction::Mark(label_end));
+        self.emit(Instruction::Discard);
+        self.emit(Instruction::Return);
+    }
+
+    // [a b] -> [a >> b]
+    // 2^b で割ると 2^b が溢れることがあるので、1 ビットずつ割る
+    // a が 0 か -1 になったら、それ以上シフトしても変わらない
+    fn compile_runtime_shift_right(&mut self) {
+        let label_loop = format!("{}.loop", Runtime::ShiftRight.label());
+        let label_end = format!("{}.end", Runtime::ShiftRight.label());
+        self.emit_check_shift();
+        self.emit(Instruction::Mark(label_loop.clone()));
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::JumpZero(label_end.clone()));
+        self.emit(Instruction::Copy(1));
+        self.emit_is_sign_only();
+        self.emit(Instruction::JumpZero(label_end.clone()));
+        self.emit(Instruction::Push(1));
+        self.emit(Instruction::Sub);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Push(2));
+        self.emit(Instruction::Div);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Jump(label_loop));
+        self.emit(Instruction::Mark(label_end));
+        self.emit(Instruction::Discard);
+        self.emit(Instruction::Return);
+    }
+
+    // [x y] -> [x op y] (x, y は 0 か 1)
+    fn emit_bit_combination(&mut self, runtime: &Runtime) {
+        match runtime {
+            Runtime::BitAnd => self.emit(Instruction::Mul),
+            // x + y - x * y, x + y - 2 * x * y
+            Runtime::BitOr | Runtime::BitXor => {
+                self.emit(Instruction::Copy(1));
+                self.emit(Instruction::Copy(1));
+                self.emit(Instruction::Mul);
+                self.emit(Instruction::Push(if let Runtime::BitOr = runtime {
+                    -1
+                } else {
+                    -2
+                }));
+                self.emit(Instruction::Mul);
+                self.emit(Instruction::Add);
+                self.emit(Instruction::Add);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // whitespace にはビット演算が無いので、下位のビットから再帰的に計算する
+    // f(a, b) = bit(a % 2, b % 2) + 2 * f(a / 2, b / 2)
+    // 割り続けると a, b は符号 (0 か -1) だけが残るので、そこで止める
+    // NOTE: bigint では桁数に上限が無いので、回数を固定してはいけない
+    // [a b] -> [a op b]
+    fn compile_runtime_bit_operation(&mut self, runtime: &Runtime) {
+        let label_step = format!("{}.step", runtime.label());
+        let label_check = format!("{}.check", runtime.label());
+        let label_sign = format!("{}.sign", runtime.label());
+        // x が 0 か -1 であることと x - x / 2 == 0 は同値
+        self.emit(Instruction::Copy(1));
+        self.emit_is_sign_only();
+        self.emit(Instruction::JumpZero(label_check.clone()));
+        // [a b] -> [a b bit(a % 2, b % 2) a/2 b/2]
+        self.emit(Instruction::Mark(label_step.clone()));
+        for _ in 0..2 {
+            self.emit(Instruction::Copy(1));
+            self.emit(Instruction::Push(2));
+            self.emit(Instruction::Mod);
+        }
+        self.emit_bit_combination(runtime);
+        for _ in 0..2 {
+            self.emit(Instruction::Copy(2));
+            self.emit(Instruction::Push(2));
+            self.emit(Instruction::Div);
+        }
+        self.emit(Instruction::Call(runtime.label()));
+        self.emit(Instruction::Push(2));
+        self.emit(Instruction::Mul);
+        self.emit(Instruction::Add);
+        self.emit(Instruction::Slide(2));
+        self.emit(Instruction::Return);
+        // a は 0 か -1
+        self.emit(Instruction::Mark(label_check));
+        self.emit(Instruction::Dup);
+        self.emit_is_sign_only();
+        self.emit(Instruction::JumpZero(label_sign.clone()));
+        self.emit(Instruction::Jump(label_step));
+        // [a b] -> [-(bit(-a, -b))]
+        self.emit(Instruction::Mark(label_sign));
+        self.emit(Instruction::Push(-1));
+        self.emit(Instruction::Mul);
+        self.emit(Instruction::Swap);
+        self.emit(Instruction::Push(-1));
+        self.emit(Instruction::Mul);
+        self.emit_bit_combination(runtime);
+        self.emit(Instruction::Push(-1));
+        self.emit(Instruction::Mul);
+        self.emit(Instruction::Return);
+    }
+
+    // [x] -> [x - x / 2]
+    fn emit_is_sign_only(&mut self) {
+        self.emit(Instruction::Dup);
+        self.emit(Instruction::Push(2));
+        self.emit(Instruction::Div);
+        self.emit(Instruction::Sub);
+    }
+
+    fn compile(mut self, scope: &Scope) -> Vec<Instruction> {
+        // NOTE: 未初期化のヒープは 0 なので、大域変数の初期化は不要
+        let mut frame_begin = HEAP_BEGIN;
+        for v in scope.variables.iter() {
+            let size = v.array_size.unwrap_or(1);
+            self.globals
+                .insert(v.identifier.clone(), (frame_begin, size));
+            frame_begin += size;
+        }
+        self.emit(Instruction::Push(HEAP_FRAME_POINTER));
+        self.emit(Instruction::Push(frame_begin));
+        self.emit(Instruction::Store);
+        self.emit(Instruction::Push(HEAP_STACK_POINTER));
+        self.emit(Instruction::Push(frame_begin));
+        self.emit(Instruction::Store);
+        if scope.initializer.is_some() {
+            self.emit(Instruction::Call(LABEL_GLOBAL_INITIALIZER.to_owned()));
+            self.emit(Instruction::Discard);
+        }
+        // main の戻り値はスタックに残したまま終了する
+        self.emit(Instruction::Call("main".to_owned()));
+        self.emit(Instruction::Exit);
+
+        if let Some(init) = &scope.initializer {
+            self.compile_function(LABEL_GLOBAL_INITIALIZER, init);
+        }
+        for (name, func) in scope.functions() {
+            self.compile_function(name, func);
+        }
+        let runtime = std::mem::take(&mut self.runtime);
+        for r in runtime.iter() {
+            self.compile_runtime(r);
+        }
+        self.code
+    }
+}
+
+pub fn compile(scope: &Scope) -> Vec<Instruction> {
+    Compiler::new().compile(scope)
+}
+
+// collect the values recorded by `__trace` from the heap of the finished program.
+pub fn collect_traced(heap: &BTreeMap<i64, Value>) -> BTreeMap<i64, i64> {
+    heap.range(..0)
+        .map(|(addr, v)| (-1 - addr, v.to_i64().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test;
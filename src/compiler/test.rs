@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    compile_to_whitespace, compiler::asm, parse_to_tokens, parse_to_tree, run_whitespace_testing,
+    syntactic_analyze, whitespace::Instruction,
+};
+
+#[test]
+fn test_asm_roundtrip() {
+    let instructions = vec![
+        Instruction::Mark("main".to_owned()),
+        Instruction::Push(-3),
+        Instruction::Slide(2),
+        Instruction::JumpZero("if.0.true".to_owned()),
+        Instruction::OutNum,
+        Instruction::Exit,
+    ];
+    let text = asm::emit(&instructions);
+    assert_eq!(
+        text,
+        "label main\n  push -3\n  slide 2\n  jz if.0.true\n  outnum\n  end\n"
+    );
+    assert_eq!(asm::parse(&text).ok().unwrap(), instructions);
+}
+
+#[test]
+fn test_asm_parse_comment() {
+    let res = asm::parse("# header\n  push 1 # one\n\n  outnum#\n")
+        .ok()
+        .unwrap();
+    assert_eq!(res, vec![Instruction::Push(1), Instruction::OutNum]);
+}
+
+#[test]
+fn test_asm_parse_error() {
+    let err = asm::parse("push\npush x\n  nop\nadd 1\n").err().unwrap();
+    let pointers: Vec<_> = err.iter().map(|e| e.code_pointer).collect();
+    assert_eq!(pointers, vec![Some(0), Some(10), Some(14), Some(22)]);
+}
+
+fn run(code: &str) -> (BTreeMap<i64, i64>, Vec<u8>) {
+    let t = parse_to_tokens(code).ok().unwrap();
+    let s = parse_to_tree(&t).ok().unwrap();
+    let a = syntactic_analyze(&s).ok().unwrap();
+    let (traced, _, output) = run_whitespace_testing(&compile_to_whitespace(&a), "");
+    (traced, output)
+}
+
+#[test]
+fn test_invalid_trace_key() {
+    // 負の key は記録せずに停止する
+    let (traced, output) = run("func: main() { __trace(1); __trace(-2); __trace(3); return: 0; }");
+    assert_eq!(traced, BTreeMap::from([(1, 1)]));
+    assert_eq!(output, b"invalid trace key\n");
+}
+
+#[test]
+fn test_too_large_shift() {
+    let (traced, output) = run(
+        "func: main() { let: x = 3, b = 65536; __put_int(x >> b); __trace(0); x << b + 1; __trace(1); return: 0; }",
+    );
+    assert_eq!(traced, BTreeMap::from([(0, 1)]));
+    assert_eq!(output, b"0invalid shift amount\n");
+    let (_, output) = run("func: main() { let: b = 65537; return: 1 >> b; }");
+    assert_eq!(output, b"invalid shift amount\n");
+}
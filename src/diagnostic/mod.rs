@@ -0,0 +1,348 @@
+use std::fmt::Write;
+
+use serde_json::{json, Value as JsonValue};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    base::{CodeParseError, Span},
+    interpreter::RuntimeError,
+    logger::TextCode,
+};
+
+// エラーコード。E0100 のように表示する。一度付けた番号は変えない
+//   E01xx: トークン, E02xx: 構文, E03xx: 名前や文の配置, E04xx: 実行時, E09xx: 処理系の誤り
+pub mod code {
+    pub const INVALID_TOKEN: u16 = 100;
+    pub const UNEXPECTED_TOKEN: u16 = 200;
+    pub const UNEXPECTED_END: u16 = 201;
+    pub const INVALID_ARRAY_DECLARATION: u16 = 202;
+    pub const UNKNOWN_VARIABLE: u16 = 300;
+    pub const ARRAY_MISMATCH: u16 = 301;
+    pub const UNKNOWN_FUNCTION: u16 = 302;
+    pub const ARGUMENT_COUNT: u16 = 303;
+    pub const DUPLICATED_NAME: u16 = 304;
+    pub const MISPLACED_STATEMENT: u16 = 305;
+    pub const OUTSIDE_OF_LOOP: u16 = 306;
+    pub const INVALID_STRING_LITERAL: u16 = 307;
+    pub const INVALID_ASSIGNMENT: u16 = 308;
+    pub const INVALID_MAIN: u16 = 309;
+    pub const DIVISION_BY_ZERO: u16 = 400;
+    pub const OVERFLOW: u16 = 401;
+    pub const INVALID_SHIFT: u16 = 402;
+    pub const ASSERTION_FAILED: u16 = 403;
+    pub const INDEX_OUT_OF_RANGE: u16 = 404;
+    pub const INVALID_CHARACTER: u16 = 405;
+    pub const INVALID_INPUT: u16 = 406;
+    pub const INVALID_TRACE_KEY: u16 = 407;
+    pub const OUTPUT_ERROR: u16 = 408;
+    pub const INTERNAL_ERROR: u16 = 900;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => "\x1b[1;31m",
+            Severity::Warning => "\x1b[1;33m",
+            Severity::Note => "\x1b[1;32m",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool, // 主な位置は ^、補足は - で下線を引く
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<u16>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+const BOLD: &str = "\x1b[1m";
+const GUTTER: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+// これより長い範囲は途中の行を省略する
+const MAX_SPAN_LINES: usize = 4;
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn with_code(mut self, code: u16) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_primary(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label {
+            span,
+            message,
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label {
+            span,
+            message,
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    // 最初の主な位置
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    // error[E0300]: unknown variable `x`
+    //  --> 2:7
+    //   |
+    // 2 |   x = y;
+    //   |       ^ not found
+    pub fn render(&self, text: &TextCode, color: bool) -> String {
+        let paint = |style: &str, s: &str| {
+            if color {
+                format!("{}{}{}", style, s, RESET)
+            } else {
+                s.to_owned()
+            }
+        };
+        let mut out = String::new();
+        let head = match self.code {
+            Some(code) => format!("{}[E{:04}]", self.severity.name(), code),
+            None => self.severity.name().to_owned(),
+        };
+        writeln!(
+            out,
+            "{}{}",
+            paint(self.severity.color(), &head),
+            paint(BOLD, &format!(": {}", self.message))
+        )
+        .unwrap();
+
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .filter(|l| text.contains(l.span.begin))
+            .collect();
+        let width = labels
+            .iter()
+            .map(|l| text.char_index_to_line(l.span.end.max(l.span.begin)).0 + 1)
+            .max()
+            .unwrap_or(0)
+            .to_string()
+            .len();
+        let gutter = paint(GUTTER, &format!("{} |", " ".repeat(width)));
+        if let Some(span) = self.primary_span().filter(|s| text.contains(s.begin)) {
+            let (line, column) = text.char_index_to_line(span.begin);
+            writeln!(
+                out,
+                "{}{} {}:{}",
+                " ".repeat(width),
+                paint(GUTTER, "-->"),
+                line + 1,
+                column + 1
+            )
+            .unwrap();
+        }
+        for label in labels.iter() {
+            writeln!(out, "{}", gutter).unwrap();
+            self.render_label(&mut out, text, label, width, &paint);
+        }
+        for note in self.notes.iter() {
+            writeln!(
+                out,
+                "{} {} {}",
+                " ".repeat(width),
+                paint(GUTTER, "="),
+                paint(BOLD, &format!("note: {}", note))
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // エディタや CI 向けの JSON。位置が分からなければ null
+    // line, column は render と同じく 1 始まり
+    pub fn to_json(&self, text: &TextCode, file: Option<&str>) -> JsonValue {
+        let primary = self.primary_span().filter(|s| text.contains(s.begin));
+        let position = primary.map(|s| position_to_json(text, s.begin));
+        let field = |key: &str| {
+            position
+                .as_ref()
+                .map_or(JsonValue::Null, |p| p[key].clone())
+        };
+        json!({
+            "file": file,
+            "severity": self.severity.name(),
+            "code": self.code.map(|c| format!("E{:04}", c)),
+            "message": self.message,
+            "byte_offset": field("byte_offset"),
+            "char_offset": field("char_offset"),
+            "line": field("line"),
+            "column": field("column"),
+            "labels": self
+                .labels
+                .iter()
+                .filter(|l| text.contains(l.span.begin))
+                .map(|l| json!({
+                    "primary": l.primary,
+                    "message": l.message,
+                    "begin": position_to_json(text, l.span.begin),
+                    "end": position_to_json(text, l.span.end.max(l.span.begin)),
+                }))
+                .collect::<Vec<_>>(),
+            "notes": self.notes,
+        })
+    }
+
+    fn render_label(
+        &self,
+        out: &mut String,
+        text: &TextCode,
+        label: &Label,
+        width: usize,
+        paint: &dyn Fn(&str, &str) -> String,
+    ) {
+        let (first, first_column) = text.char_index_to_line(label.span.begin);
+        // NOTE: end は範囲の外なので、最後の文字の位置から行を求める
+        let last_pointer = if label.span.end > label.span.begin {
+            label.span.end - 1
+        } else {
+            label.span.begin
+        };
+        let (last, last_column) = text.char_index_to_line(last_pointer);
+        let (mark, style) = if label.primary {
+            ("^", self.severity.color())
+        } else {
+            ("-", GUTTER)
+        };
+        for line in first..=last {
+            if last - first >= MAX_SPAN_LINES && line > first + 1 && line < last {
+                if line == first + 2 {
+                    writeln!(out, "{}", paint(GUTTER, "...")).unwrap();
+                }
+                continue;
+            }
+            let line_str = text.line(line);
+            let number = paint(GUTTER, &format!("{:>width$} |", line + 1, width = width));
+            if line_str.trim().is_empty() {
+                writeln!(out, "{}", number).unwrap();
+                continue;
+            }
+            writeln!(out, "{} {}", number, line_str).unwrap();
+            let line_len = line_str.chars().count();
+            // この行で下線を引く範囲 (文字単位)
+            let begin = if line == first {
+                first_column
+            } else {
+                line_len - line_str.trim_start().chars().count()
+            };
+            let end = if line == last {
+                last_column + 1
+            } else {
+                line_len
+            };
+            let end = end.min(line_len).max(begin + 1);
+            let prefix: String = line_str.chars().take(begin).collect();
+            let body: String = line_str.chars().skip(begin).take(end - begin).collect();
+            let underline = paint(
+                style,
+                &mark.repeat(UnicodeWidthStr::width(body.as_str()).max(1)),
+            );
+            let message = if line == last && !label.message.is_empty() {
+                format!(" {}", paint(style, &label.message))
+            } else {
+                String::new()
+            };
+            writeln!(
+                out,
+                "{} {}{}{}",
+                paint(GUTTER, &format!("{} |", " ".repeat(width))),
+                " ".repeat(UnicodeWidthStr::width(prefix.as_str())),
+                underline,
+                message
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn position_to_json(text: &TextCode, pointer: usize) -> JsonValue {
+    let (line, column) = text.char_index_to_line(pointer);
+    json!({
+        "byte_offset": text.char_index_to_byte(pointer),
+        "char_offset": pointer,
+        "line": line + 1,
+        "column": column + 1,
+    })
+}
+
+impl From<&CodeParseError> for Diagnostic {
+    fn from(e: &CodeParseError) -> Self {
+        let mut d = Diagnostic::new(Severity::Error, e.message.clone());
+        d.code = e.code;
+        let span = match (e.span, e.code_pointer) {
+            (Some(span), _) => Some(span),
+            (None, Some(p)) => Some(Span::new(p, p + 1)),
+            (None, None) => None,
+        };
+        if let Some(span) = span {
+            d = d.with_primary(span, String::new());
+        }
+        for (span, message) in e.labels.iter() {
+            d = d.with_label(*span, message.clone());
+        }
+        d
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(e: &RuntimeError) -> Self {
+        let d = Diagnostic::new(Severity::Error, e.message.clone()).with_code(e.code);
+        match (e.span, e.code_pointer) {
+            (Some(span), _) => d.with_primary(span, format!("evaluated as `{}`", e.operation)),
+            (None, Some(p)) => d.with_primary(Span::new(p, p + 1), String::new()),
+            (None, None) => d,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
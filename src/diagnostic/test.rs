@@ -0,0 +1,193 @@
+use crate::{
+    base::{CodeParseErrorInternal, Span},
+    code_parse_error,
+    diagnostic::{code, Diagnostic, Severity},
+    logger::TextCode,
+};
+
+#[test]
+fn test_render() {
+    let text = TextCode::new("func: f() {\n  x = 1;\n}\n");
+    let d = Diagnostic::new(Severity::Error, "unknown variable `x`".to_owned())
+        .with_code(code::UNKNOWN_VARIABLE)
+        .with_primary(Span::new(14, 15), "not found".to_owned());
+    assert_eq!(
+        d.render(&text, false),
+        "\
+error[E0300]: unknown variable `x`
+ --> 2:3
+  |
+2 |   x = 1;
+  |   ^ not found
+"
+    );
+}
+
+#[test]
+fn test_render_without_location() {
+    let text = TextCode::new("let: x;");
+    let d = Diagnostic::new(Severity::Warning, "unused".to_owned())
+        .with_primary(Span::new(100, 101), String::new());
+    assert_eq!(d.render(&text, false), "warning: unused\n");
+}
+
+#[test]
+fn test_render_secondary_label_and_note() {
+    let text = TextCode::new("let: ab;\nlet: ab;");
+    let d = Diagnostic::new(Severity::Error, "the name `ab` is already used".to_owned())
+        .with_primary(Span::new(14, 16), String::new())
+        .with_label(Span::new(5, 7), "first declared here".to_owned())
+        .with_note("rename one of them".to_owned());
+    assert_eq!(
+        d.render(&text, false),
+        "\
+error: the name `ab` is already used
+ --> 2:6
+  |
+2 | let: ab;
+  |      ^^
+  |
+1 | let: ab;
+  |      -- first declared here
+  = note: rename one of them
+"
+    );
+}
+
+#[test]
+fn test_render_multiline() {
+    let text = TextCode::new("x = (1 +\n  2 +\n\n  3 +\n  4 +\n  5);");
+    let d = Diagnostic::new(Severity::Error, "overflow".to_owned())
+        .with_primary(Span::new(4, 32), "here".to_owned());
+    assert_eq!(
+        d.render(&text, false),
+        "\
+error: overflow
+ --> 1:5
+  |
+1 | x = (1 +
+  |     ^^^^
+2 |   2 +
+  |   ^^^
+...
+6 |   5);
+  |   ^^ here
+"
+    );
+
+    // 短い範囲は省略しない
+    let d = Diagnostic::new(Severity::Error, "overflow".to_owned())
+        .with_primary(Span::new(4, 19), String::new());
+    assert_eq!(
+        d.render(&text, false),
+        "\
+error: overflow
+ --> 1:5
+  |
+1 | x = (1 +
+  |     ^^^^
+2 |   2 +
+  |   ^^^
+3 |
+4 |   3 +
+  |   ^
+"
+    );
+}
+
+#[test]
+fn test_render_wide_char() {
+    let text = TextCode::new("__puts(\"あい\") + う;");
+    let d = Diagnostic::new(Severity::Error, "invalid char".to_owned())
+        .with_primary(Span::new(15, 16), String::new());
+    assert_eq!(
+        d.render(&text, false),
+        "\
+error: invalid char
+ --> 1:16
+  |
+1 | __puts(\"あい\") + う;
+  |                  ^^
+"
+    );
+}
+
+#[test]
+fn test_render_color() {
+    let text = TextCode::new("x;");
+    let d = Diagnostic::new(Severity::Error, "oops".to_owned())
+        .with_primary(Span::new(0, 1), String::new());
+    let out = d.render(&text, true);
+    assert!(out.starts_with("\x1b[1;31merror\x1b[0m"));
+    assert!(out.contains("\x1b[1;31m^\x1b[0m"));
+    assert!(!d.render(&text, false).contains('\x1b'));
+}
+
+#[test]
+fn test_from_code_parse_error() {
+    let e = code_parse_error!(3, "unexpected token".to_owned())
+        .with_code(code::UNEXPECTED_TOKEN)
+        .shrink();
+    let d = Diagnostic::from(&e);
+    assert_eq!(d.code, Some(code::UNEXPECTED_TOKEN));
+    assert_eq!(d.primary_span(), Some(Span::new(3, 4)));
+
+    let e = code_parse_error!(3, "duplicated".to_owned())
+        .with_span(Span::new(3, 5))
+        .with_label(Span::new(0, 2), "first".to_owned())
+        .shrink();
+    let d = Diagnostic::from(&e);
+    assert_eq!(d.code, None);
+    assert_eq!(d.primary_span(), Some(Span::new(3, 5)));
+    assert_eq!(d.labels.len(), 2);
+    assert!(!d.labels[1].primary);
+}
+
+#[test]
+fn test_to_json() {
+    let text = TextCode::new("let: あ;\nlet: x = y;");
+    let d = Diagnostic::new(Severity::Error, "unknown variable `y`".to_owned())
+        .with_code(code::UNKNOWN_VARIABLE)
+        .with_primary(Span::new(17, 18), String::new())
+        .with_label(Span::new(5, 6), "here".to_owned());
+    assert_eq!(
+        d.to_json(&text, Some("a.ns")),
+        serde_json::json!({
+            "file": "a.ns",
+            "severity": "error",
+            "code": "E0300",
+            "message": "unknown variable `y`",
+            "byte_offset": 19,
+            "char_offset": 17,
+            "line": 2,
+            "column": 10,
+            "labels": [
+                {
+                    "primary": true,
+                    "message": "",
+                    "begin": {"byte_offset": 19, "char_offset": 17, "line": 2, "column": 10},
+                    "end": {"byte_offset": 20, "char_offset": 18, "line": 2, "column": 11},
+                },
+                {
+                    "primary": false,
+                    "message": "here",
+                    "begin": {"byte_offset": 5, "char_offset": 5, "line": 1, "column": 6},
+                    "end": {"byte_offset": 8, "char_offset": 6, "line": 1, "column": 7},
+                },
+            ],
+            "notes": [],
+        })
+    );
+}
+
+#[test]
+fn test_to_json_without_location() {
+    let text = TextCode::new("");
+    let d = Diagnostic::new(Severity::Error, "unexpected end of input".to_owned())
+        .with_primary(Span::new(0, 0), String::new());
+    let json = d.to_json(&text, None);
+    assert_eq!(json["file"], serde_json::Value::Null);
+    assert_eq!(json["code"], serde_json::Value::Null);
+    assert_eq!(json["line"], serde_json::Value::Null);
+    assert_eq!(json["labels"], serde_json::json!([]));
+}
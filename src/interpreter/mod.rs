@@ -1,6 +1,13 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt,
+    io::{BufRead, Write},
+};
 
 use crate::{
+    base::{read_char, read_num, Span, Value, MAX_SHIFT},
+    diagnostic::code,
     syntactic_analyzer::{ExecExpression, ExecStatement, Function, Scope},
     tree_parser::{Operator1, Operator2},
 };
@@ -8,14 +15,15 @@ use crate::{
 // Block(Vec<Statement>) の評価結果
 enum Flow {
     Proceed,
-    Return(i64),
+    Return(Value),
     Continue,
     Break,
+    Error(RuntimeError),
 }
 
 // Expression の評価結果
 enum ExpressionFlow {
-    Value(i64),
+    Value(Value),
     Jump(Flow),
 }
 
@@ -28,94 +36,422 @@ macro_rules! try_expr {
     };
 }
 
-pub struct Environment {
+// 整数演算が溢れたときの扱い
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    Checked,  // 実行時エラーにする
+    Wrapping, // 2 の補数で丸める
+}
+
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub message: String,             // e.g. "division by zero"
+    pub code: u16,                   // See, diagnostic::code
+    pub operation: String,           // e.g. "7 / 0"
+    pub code_pointer: Option<usize>, // 演算子 (関数呼び出しなら関数名) の位置
+    pub span: Option<Span>,          // 演算の範囲
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: `{}`", self.message, self.operation)
+    }
+}
+
+fn runtime_error(
+    message: &str,
+    operation: String,
+    code_pointer: usize,
+    span: Span,
+) -> ExpressionFlow {
+    ExpressionFlow::Jump(Flow::Error(RuntimeError {
+        message: message.to_owned(),
+        code: error_code(message),
+        operation,
+        code_pointer: Some(code_pointer),
+        span: Some(span),
+    }))
+}
+
+// 入出力に失敗した。message は入出力のエラーをそのまま使う
+fn io_error(code: u16, message: String, operation: &str, span: Span) -> ExpressionFlow {
+    ExpressionFlow::Jump(Flow::Error(RuntimeError {
+        message,
+        code,
+        operation: operation.to_owned(),
+        code_pointer: Some(span.begin),
+        span: Some(span),
+    }))
+}
+
+pub struct Environment<'io> {
     pub traced: BTreeMap<i64, i64>,
+    pub globals: BTreeMap<String, Value>,
+    pub global_arrays: BTreeMap<String, Vec<Value>>,
+    pub input: Box<dyn BufRead + 'io>, // __read_int, __read_char の入力
+    pub output: Box<dyn Write + 'io>,  // __put_int, __put_char の出力
+    pub arithmetic: ArithmeticMode,
 }
 
-impl Environment {
-    pub fn new() -> Self {
+impl<'io> Environment<'io> {
+    pub fn new(input: Box<dyn BufRead + 'io>, output: Box<dyn Write + 'io>) -> Self {
         Environment {
             traced: BTreeMap::new(),
+            globals: BTreeMap::new(),
+            global_arrays: BTreeMap::new(),
+            input,
+            output,
+            arithmetic: ArithmeticMode::Checked,
         }
     }
 }
 
-struct LocalEnvironment<'a, 'aenv> {
-    env: &'aenv mut Environment,
+struct LocalEnvironment<'a, 'aenv, 'io> {
+    env: &'aenv mut Environment<'io>,
     root_scope: &'a Scope,
-    current_scope: &'a Scope,
-    variables: BTreeMap<String, i64>,
+    variables: BTreeMap<String, Value>,
+    arrays: BTreeMap<String, Vec<Value>>,
+}
+
+fn bool_to_int(x: bool) -> Value {
+    Value::from(if x { 1 } else { 0 })
 }
 
-fn bool_to_int(x: bool) -> i64 {
-    if x {
-        1
-    } else {
-        0
+const DIVISION_BY_ZERO: &str = "division by zero";
+const OVERFLOW: &str = "arithmetic overflow";
+const INVALID_SHIFT: &str = "invalid shift amount";
+const ASSERTION_FAILED: &str = "assertion failed";
+const INDEX_OUT_OF_RANGE: &str = "index out of range";
+const INVALID_CHARACTER: &str = "invalid character";
+const INVALID_TRACE_KEY: &str = "invalid trace key";
+
+fn error_code(message: &str) -> u16 {
+    match message {
+        DIVISION_BY_ZERO => code::DIVISION_BY_ZERO,
+        OVERFLOW => code::OVERFLOW,
+        INVALID_SHIFT => code::INVALID_SHIFT,
+        ASSERTION_FAILED => code::ASSERTION_FAILED,
+        INDEX_OUT_OF_RANGE => code::INDEX_OUT_OF_RANGE,
+        INVALID_CHARACTER => code::INVALID_CHARACTER,
+        INVALID_TRACE_KEY => code::INVALID_TRACE_KEY,
+        _ => unreachable!(),
     }
 }
 
-impl LocalEnvironment<'_, '_> {
-    fn new_func<'a, 'aenv>(
-        env: &'aenv mut Environment,
+// 代入以外の二項演算
+fn operate(
+    mode: ArithmeticMode,
+    op: &Operator2,
+    v1: &Value,
+    v2: &Value,
+) -> Result<Value, &'static str> {
+    let arithmetic = |checked: fn(&Value, &Value) -> Option<Value>,
+                      wrapping: fn(&Value, &Value) -> Value| match mode {
+        ArithmeticMode::Checked => checked(v1, v2).ok_or(OVERFLOW),
+        ArithmeticMode::Wrapping => Ok(wrapping(v1, v2)),
+    };
+    // シフト量は MAX_SHIFT 以下の非負整数
+    let shift = || {
+        v2.to_i64()
+            .filter(|b| (0..=MAX_SHIFT).contains(b))
+            .map(|b| b as u32)
+            .ok_or(INVALID_SHIFT)
+    };
+    match op {
+        Operator2::Plus => arithmetic(Value::checked_add, Value::wrapping_add),
+        Operator2::Minus => arithmetic(Value::checked_sub, Value::wrapping_sub),
+        Operator2::Multiply => arithmetic(Value::checked_mul, Value::wrapping_mul),
+        Operator2::Divide | Operator2::Modulo if v2.is_zero() => Err(DIVISION_BY_ZERO),
+        Operator2::Divide => arithmetic(Value::checked_div_floor, Value::wrapping_div_floor),
+        Operator2::Modulo => arithmetic(Value::checked_mod_floor, Value::wrapping_mod_floor),
+        Operator2::BitAnd => Ok(v1.bit_and(v2)),
+        Operator2::BitOr => Ok(v1.bit_or(v2)),
+        Operator2::BitXor => Ok(v1.bit_xor(v2)),
+        Operator2::ShiftLeft => {
+            let b = shift()?;
+            match mode {
+                ArithmeticMode::Checked => v1.checked_shl(b).ok_or(OVERFLOW),
+                ArithmeticMode::Wrapping => Ok(v1.wrapping_shl(b)),
+            }
+        }
+        Operator2::ShiftRight => Ok(v1.shr(shift()?)),
+        Operator2::Equal => Ok(bool_to_int(v1 == v2)),
+        Operator2::NotEqual => Ok(bool_to_int(v1 != v2)),
+        Operator2::Less => Ok(bool_to_int(v1 < v2)),
+        Operator2::LessEqual => Ok(bool_to_int(v1 <= v2)),
+        Operator2::Greater => Ok(bool_to_int(v1 > v2)),
+        Operator2::GreaterEqual => Ok(bool_to_int(v1 >= v2)),
+        // 代入と短絡評価は interpret_operation2 で処理する
+        _ => unreachable!(),
+    }
+}
+
+// source_name: ソース上の配列の名前, span: 配列の要素 `a[i]` の範囲
+fn check_index(
+    source_name: &str,
+    index: &Value,
+    array: &[Value],
+    span: Span,
+) -> Result<usize, ExpressionFlow> {
+    match index.to_i64().and_then(|i| usize::try_from(i).ok()) {
+        Some(i) if i < array.len() => Ok(i),
+        _ => Err(runtime_error(
+            INDEX_OUT_OF_RANGE,
+            format!("{}[{}]", source_name, index),
+            span.begin,
+            span,
+        )),
+    }
+}
+
+impl LocalEnvironment<'_, '_, '_> {
+    fn new_func<'a, 'aenv, 'io>(
+        env: &'aenv mut Environment<'io>,
         root_scope: &'a Scope,
         func: &'a Function,
-        args: &Vec<i64>,
-    ) -> LocalEnvironment<'a, 'aenv> {
-        let mut variables = BTreeMap::<String, i64>::new();
+        args: Vec<Value>,
+    ) -> LocalEnvironment<'a, 'aenv, 'io> {
+        let mut variables = BTreeMap::<String, Value>::new();
+        let mut arrays = BTreeMap::<String, Vec<Value>>::new();
         for id_eval in func.args.iter().zip(args) {
-            variables.insert(id_eval.0.clone(), *id_eval.1);
+            variables.insert(id_eval.0.clone(), id_eval.1);
         }
         for v in func.scope.variables.iter() {
-            if !variables.contains_key(&v.identifier) {
-                variables.insert(v.identifier.clone(), 0);
+            if let Some(size) = v.array_size {
+                arrays.insert(v.identifier.clone(), vec![Value::from(0); size as usize]);
+            } else if !variables.contains_key(&v.identifier) {
+                variables.insert(v.identifier.clone(), Value::from(0));
             }
         }
         LocalEnvironment {
             env,
             root_scope,
-            current_scope: &func.scope,
             variables,
+            arrays,
+        }
+    }
+
+    fn array_mut(&mut self, name: &str, is_global: bool) -> &mut Vec<Value> {
+        let arrays = if is_global {
+            &mut self.env.global_arrays
+        } else {
+            &mut self.arrays
+        };
+        match arrays.get_mut(name) {
+            Some(x) => x,
+            None => panic!("syntax error: unknown array name `{}`", name),
+        }
+    }
+
+    fn interpret_array_element(
+        &mut self,
+        name: &str,
+        source_name: &str,
+        index: &ExecExpression,
+        is_global: bool,
+        span: Span,
+    ) -> ExpressionFlow {
+        let index = try_expr!(self.interpret_expression(index));
+        let array = self.array_mut(name, is_global);
+        match check_index(source_name, &index, array, span) {
+            Ok(i) => ExpressionFlow::Value(array[i].clone()),
+            Err(e) => e,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn interpret_assign_array_element(
+        &mut self,
+        name: &str,
+        source_name: &str,
+        index: &ExecExpression,
+        is_global: bool,
+        target_span: Span,
+        compound: Option<&Operator2>,
+        expr: &ExecExpression,
+        code_pointer: usize,
+        span: Span,
+    ) -> ExpressionFlow {
+        // NOTE: 添字を先に評価する
+        let index = try_expr!(self.interpret_expression(index));
+        let array = self.array_mut(name, is_global);
+        let i = match check_index(source_name, &index, array, target_span) {
+            Ok(i) => i,
+            Err(e) => return e,
+        };
+        let old = self.array_mut(name, is_global)[i].clone();
+        let v = try_expr!(self.interpret_expression(expr));
+        let v = try_expr!(self.assigned_value(compound, old, v, code_pointer, span));
+        self.array_mut(name, is_global)[i] = v.clone();
+        ExpressionFlow::Value(v)
+    }
+
+    // NOTE: 未初期化のグローバル変数は 0
+    fn global(&self, name: &str) -> Value {
+        match self.env.globals.get(name) {
+            Some(x) => x.clone(),
+            None => Value::from(0),
+        }
+    }
+
+    fn write_output(
+        &mut self,
+        args: fmt::Arguments,
+        operation: &str,
+        span: Span,
+    ) -> Result<(), ExpressionFlow> {
+        self.env
+            .output
+            .write_fmt(args)
+            .map_err(|e| io_error(code::OUTPUT_ERROR, e.to_string(), operation, span))
+    }
+
+    // 入力を待つ前に、それまでの出力を書き出す
+    fn flush_output(&mut self, operation: &str, span: Span) -> Result<(), ExpressionFlow> {
+        self.env
+            .output
+            .flush()
+            .map_err(|e| io_error(code::OUTPUT_ERROR, e.to_string(), operation, span))
+    }
+
     fn interpret_call_function(
         &mut self,
-        id: &String,
-        args: &Vec<Box<ExecExpression>>,
+        id: &str,
+        args: &[ExecExpression],
+        span: Span,
     ) -> ExpressionFlow {
-        match id.as_str() {
+        match id {
             "__clog" => {
                 let a = try_expr!(self.interpret_expression(args.first().unwrap()));
-                println!("__clog: {}", a);
+                // NOTE: コンパイル結果と同じ出力になるよう、プログラムの出力に書く
+                let operation = format!("__clog({})", a);
+                if let Err(e) = self.write_output(format_args!("__clog: {}\n", a), &operation, span)
+                {
+                    return e;
+                }
                 ExpressionFlow::Value(a)
             }
             "__assert" => {
                 let a = try_expr!(self.interpret_expression(args.first().unwrap()));
-                if a == 0 {
-                    // TODO: 気の利いたログを出せない
-                    panic!("assertion failed: {} == 0", a);
+                if a.is_zero() {
+                    return runtime_error(
+                        ASSERTION_FAILED,
+                        format!("__assert({})", a),
+                        span.begin,
+                        span,
+                    );
                 }
                 ExpressionFlow::Value(a)
             }
             "__assert_not" => {
                 let a = try_expr!(self.interpret_expression(args.first().unwrap()));
-                if a != 0 {
-                    // TODO: 気の利いたログを出せない
-                    panic!("assertion failed: {} != 0", a);
+                if !a.is_zero() {
+                    return runtime_error(
+                        ASSERTION_FAILED,
+                        format!("__assert_not({})", a),
+                        span.begin,
+                        span,
+                    );
                 }
                 ExpressionFlow::Value(a)
             }
             "__trace" => {
                 // TODO: 未だ比較演算子を実装していないので not
                 let key = try_expr!(self.interpret_expression(args.first().unwrap()));
+                // NOTE: コンパイル結果は負のアドレスに記録するので、key は非負に限る
+                let key = match key.to_i64() {
+                    Some(x) if x >= 0 => x,
+                    _ => {
+                        return runtime_error(
+                            INVALID_TRACE_KEY,
+                            format!("__trace({})", key),
+                            span.begin,
+                            span,
+                        )
+                    }
+                };
                 let traced = &mut self.env.traced;
                 if let Some(v) = traced.get_mut(&key) {
                     *v += 1;
                 } else {
                     traced.insert(key, 1);
                 }
-                ExpressionFlow::Value(0)
+                ExpressionFlow::Value(Value::from(0))
+            }
+            "__put_int" => {
+                let a = try_expr!(self.interpret_expression(args.first().unwrap()));
+                let operation = format!("__put_int({})", a);
+                if let Err(e) = self.write_output(format_args!("{}", a), &operation, span) {
+                    return e;
+                }
+                ExpressionFlow::Value(Value::from(0))
+            }
+            "__put_char" => {
+                let a = try_expr!(self.interpret_expression(args.first().unwrap()));
+                // NOTE: whitespace の outchar と同様、Unicode のコードポイントとして出力する
+                let c = match a
+                    .to_i64()
+                    .and_then(|a| u32::try_from(a).ok())
+                    .and_then(std::char::from_u32)
+                {
+                    Some(c) => c,
+                    None => {
+                        return runtime_error(
+                            INVALID_CHARACTER,
+                            format!("__put_char({})", a),
+                            span.begin,
+                            span,
+                        )
+                    }
+                };
+                let operation = format!("__put_char({})", a);
+                if let Err(e) = self.write_output(format_args!("{}", c), &operation, span) {
+                    return e;
+                }
+                ExpressionFlow::Value(Value::from(0))
+            }
+            "__puts" => {
+                // NOTE: 引数は文字のコードポイントに展開されている
+                for a in args {
+                    let a = try_expr!(self.interpret_expression(a));
+                    let c = match a
+                        .to_i64()
+                        .and_then(|a| u32::try_from(a).ok())
+                        .and_then(std::char::from_u32)
+                    {
+                        Some(c) => c,
+                        None => {
+                            return runtime_error(
+                                INVALID_CHARACTER,
+                                format!("__puts({})", a),
+                                span.begin,
+                                span,
+                            )
+                        }
+                    };
+                    if let Err(e) = self.write_output(format_args!("{}", c), "__puts", span) {
+                        return e;
+                    }
+                }
+                ExpressionFlow::Value(Value::from(0))
+            }
+            "__read_int" => {
+                if let Err(e) = self.flush_output("__read_int()", span) {
+                    return e;
+                }
+                match read_num(&mut self.env.input) {
+                    Ok(x) => ExpressionFlow::Value(x),
+                    Err(e) => io_error(code::INVALID_INPUT, e, "__read_int()", span),
+                }
+            }
+            "__read_char" => {
+                if let Err(e) = self.flush_output("__read_char()", span) {
+                    return e;
+                }
+                match read_char(&mut self.env.input) {
+                    Ok(x) => ExpressionFlow::Value(Value::from(x)),
+                    Err(e) => io_error(code::INVALID_INPUT, e, "__read_char()", span),
+                }
             }
             _ => self.interpret_call_user_function(id, args),
         }
@@ -123,36 +459,32 @@ impl LocalEnvironment<'_, '_> {
 
     fn interpret_call_user_function(
         &mut self,
-        id: &String,
-        args: &Vec<Box<ExecExpression>>,
+        id: &str,
+        args: &[ExecExpression],
     ) -> ExpressionFlow {
-        let mut arg_values = Vec::new();
-        arg_values.reserve(args.len());
+        let mut arg_values = Vec::with_capacity(args.len());
         for a in args {
             // note: We can't use `map` because some args may say `return`/`break`;
             arg_values.push(try_expr!(self.interpret_expression(a)));
         }
-        let func = self.root_scope.get_function(id.as_str()).unwrap();
+        let func = self.root_scope.get_function(id).unwrap();
 
-        let mut env = LocalEnvironment::new_func(self.env, self.root_scope, &func, &arg_values);
+        let mut env = LocalEnvironment::new_func(self.env, self.root_scope, func, arg_values);
         match env.interpret_statements(&func.code) {
-            Flow::Proceed => ExpressionFlow::Value(0),
+            Flow::Proceed => ExpressionFlow::Value(Value::from(0)),
             Flow::Continue => panic!("internal error: unexpected continue"),
             Flow::Break => panic!("internal error: unexpected break"),
-            other => ExpressionFlow::Jump(other),
+            Flow::Return(v) => ExpressionFlow::Value(v),
+            Flow::Error(e) => ExpressionFlow::Jump(Flow::Error(e)),
         }
     }
 
-    fn interpret_while(
-        &mut self,
-        cond: &Box<ExecExpression>,
-        code: &Vec<ExecStatement>,
-    ) -> ExpressionFlow {
+    fn interpret_while(&mut self, cond: &ExecExpression, code: &[ExecStatement]) -> ExpressionFlow {
         loop {
             let cond = match self.interpret_expression(cond) {
                 ExpressionFlow::Value(e) => e,
-                ExpressionFlow::Jump(Flow::Return(x)) => {
-                    return ExpressionFlow::Jump(Flow::Return(x))
+                ExpressionFlow::Jump(f @ (Flow::Return(_) | Flow::Error(_))) => {
+                    return ExpressionFlow::Jump(f)
                 }
                 // TODO: exclude on comile-time.
                 ExpressionFlow::Jump(Flow::Continue) => panic!(
@@ -165,28 +497,35 @@ impl LocalEnvironment<'_, '_> {
                     panic!("internal error: unexpected Flow::Proceed")
                 }
             };
-            if cond == 0 {
+            if cond.is_zero() {
                 break;
             }
             match self.interpret_statements(code) {
                 Flow::Proceed => (),
-                Flow::Return(v) => return ExpressionFlow::Value(v),
+                f @ (Flow::Return(_) | Flow::Error(_)) => return ExpressionFlow::Jump(f),
                 Flow::Continue => continue,
                 Flow::Break => break,
             }
         }
-        ExpressionFlow::Value(0) // TODO: spec
+        ExpressionFlow::Value(Value::from(0)) // TODO: spec
     }
 
     fn interpret_if(
         &mut self,
-        cond: &Box<ExecExpression>,
-        stats_true: &Vec<ExecStatement>,
-        stats_false: &Vec<ExecStatement>,
+        branches: &[(Box<ExecExpression>, Vec<ExecStatement>)],
+        stats_else: &[ExecStatement],
     ) -> ExpressionFlow {
-        let cond = try_expr!(self.interpret_expression(cond));
-        match self.interpret_statements(if cond == 0 { stats_true } else { stats_false }) {
-            Flow::Proceed => ExpressionFlow::Value(0),
+        // 条件が 0 になる最初の分岐を実行する
+        let mut stats = stats_else;
+        for (cond, stats_branch) in branches {
+            let cond = try_expr!(self.interpret_expression(cond));
+            if cond.is_zero() {
+                stats = stats_branch;
+                break;
+            }
+        }
+        match self.interpret_statements(stats) {
+            Flow::Proceed => ExpressionFlow::Value(Value::from(0)),
             other => ExpressionFlow::Jump(other),
         }
     }
@@ -194,93 +533,184 @@ impl LocalEnvironment<'_, '_> {
     fn interpret_operation1(
         &mut self,
         op: &Operator1,
-        expr1: &Box<ExecExpression>,
+        expr1: &ExecExpression,
+        code_pointer: usize,
+        span: Span,
     ) -> ExpressionFlow {
         let v1 = try_expr!(self.interpret_expression(expr1));
         let res = match op {
-            Operator1::Negative => -v1,
+            Operator1::Negative => match self.env.arithmetic {
+                ArithmeticMode::Checked => v1.checked_neg(),
+                ArithmeticMode::Wrapping => Some(v1.wrapping_neg()),
+            },
+            Operator1::Not => Some(bool_to_int(v1.is_zero())),
         };
-        ExpressionFlow::Value(res)
+        match res {
+            Some(x) => ExpressionFlow::Value(x),
+            None => runtime_error(OVERFLOW, format!("{}({})", op, v1), code_pointer, span),
+        }
+    }
+
+    // 代入以外の二項演算
+    fn interpret_arithmetic(
+        &self,
+        op: &Operator2,
+        v1: &Value,
+        v2: &Value,
+        code_pointer: usize,
+        span: Span,
+    ) -> ExpressionFlow {
+        match operate(self.env.arithmetic, op, v1, v2) {
+            Ok(x) => ExpressionFlow::Value(x),
+            Err(message) => {
+                runtime_error(message, format!("{} {} {}", v1, op, v2), code_pointer, span)
+            }
+        }
+    }
+
+    // 複合代入なら元の値 old と演算する
+    fn assigned_value(
+        &self,
+        compound: Option<&Operator2>,
+        old: Value,
+        v: Value,
+        code_pointer: usize,
+        span: Span,
+    ) -> ExpressionFlow {
+        match compound {
+            Some(op) => self.interpret_arithmetic(op, &old, &v, code_pointer, span),
+            None => ExpressionFlow::Value(v),
+        }
     }
 
     fn interpret_operation2(
         &mut self,
         op: &Operator2,
-        expr1: &Box<ExecExpression>,
-        expr2: &Box<ExecExpression>,
+        expr1: &ExecExpression,
+        expr2: &ExecExpression,
+        code_pointer: usize,
+        span: Span,
     ) -> ExpressionFlow {
-        if let Operator2::Assign = op {
-            if let ExecExpression::Variable(name) = expr1.as_ref() {
-                if self.variables.contains_key(name) {
-                    // todo: more nice impl
-                    // todo: should be checked not in runtime.
+        if op.is_assign() {
+            // NOTE: 左辺は 1 度だけ評価する
+            let compound = op.compound_operator();
+            let compound = compound.as_ref();
+            match expr1 {
+                ExecExpression::Variable(name, _) => {
+                    let old = match self.variables.get(name) {
+                        Some(x) => x.clone(),
+                        None => panic!("syntax error: unknown variable name `{}`", name),
+                    };
                     let v = try_expr!(self.interpret_expression(expr2));
-                    self.variables.insert(name.clone(), v);
+                    let v = try_expr!(self.assigned_value(compound, old, v, code_pointer, span));
+                    self.variables.insert(name.clone(), v.clone());
                     return ExpressionFlow::Value(v);
-                } else {
-                    panic!("syntax error: unknown variable name `{}`", name)
                 }
-            } else {
-                panic!("runtime error: left value is not variable");
+                ExecExpression::GlobalVariable(name, _) => {
+                    let old = self.global(name);
+                    let v = try_expr!(self.interpret_expression(expr2));
+                    let v = try_expr!(self.assigned_value(compound, old, v, code_pointer, span));
+                    self.env.globals.insert(name.clone(), v.clone());
+                    return ExpressionFlow::Value(v);
+                }
+                ExecExpression::ArrayElement(name, source_name, index, target_span) => {
+                    return self.interpret_assign_array_element(
+                        name,
+                        source_name,
+                        index,
+                        false,
+                        *target_span,
+                        compound,
+                        expr2,
+                        code_pointer,
+                        span,
+                    )
+                }
+                ExecExpression::GlobalArrayElement(name, source_name, index, target_span) => {
+                    return self.interpret_assign_array_element(
+                        name,
+                        source_name,
+                        index,
+                        true,
+                        *target_span,
+                        compound,
+                        expr2,
+                        code_pointer,
+                        span,
+                    )
+                }
+                _ => panic!("runtime error: left value is not variable"),
             }
         }
         let v1 = try_expr!(self.interpret_expression(expr1));
+        // 短絡評価。右辺は評価しないこともある
+        match (op, !v1.is_zero()) {
+            (Operator2::LogicalAnd, false) => return ExpressionFlow::Value(Value::from(0)),
+            (Operator2::LogicalOr, true) => return ExpressionFlow::Value(Value::from(1)),
+            (Operator2::LogicalAnd | Operator2::LogicalOr, _) => {
+                let v2 = try_expr!(self.interpret_expression(expr2));
+                return ExpressionFlow::Value(bool_to_int(!v2.is_zero()));
+            }
+            _ => (),
+        }
         let v2 = try_expr!(self.interpret_expression(expr2));
-        let res = match op {
-            Operator2::Plus => v1 + v2,
-            Operator2::Minus => v1 - v2,
-            Operator2::Multiply => v1 * v2,
-            Operator2::Divide => v1 / v2,
-            Operator2::Assign => unreachable!(),
-            Operator2::Equal => bool_to_int(v1 == v2),
-            Operator2::NotEqual => bool_to_int(v1 != v2),
-            Operator2::Less => bool_to_int(v1 < v2),
-            Operator2::LessEqual => bool_to_int(v1 <= v2),
-            Operator2::Greater => bool_to_int(v1 > v2),
-            Operator2::GreaterEqual => bool_to_int(v1 >= v2),
-        };
-        ExpressionFlow::Value(res)
+        self.interpret_arithmetic(op, &v1, &v2, code_pointer, span)
     }
 
     // if while を式にした以上、式の中に文が含まれる可能性がある…
-    fn interpret_expression(&mut self, expr: &Box<ExecExpression>) -> ExpressionFlow {
-        match expr.as_ref() {
-            ExecExpression::Operation1(op, expr1) => self.interpret_operation1(op, expr1),
-            ExecExpression::Operation2(op, expr1, expr2) => {
-                self.interpret_operation2(op, expr1, expr2)
+    fn interpret_expression(&mut self, expr: &ExecExpression) -> ExpressionFlow {
+        match expr {
+            ExecExpression::Operation1(op, expr1, code_pointer, span) => {
+                self.interpret_operation1(op, expr1, *code_pointer, *span)
             }
-            ExecExpression::Function(id, args) => self.interpret_call_function(id, args),
-            ExecExpression::Factor(v) => ExpressionFlow::Value(*v),
-            ExecExpression::Variable(name) => {
+            ExecExpression::Operation2(op, expr1, expr2, code_pointer, span) => {
+                self.interpret_operation2(op, expr1, expr2, *code_pointer, *span)
+            }
+            ExecExpression::Function(id, args, span) => {
+                self.interpret_call_function(id, args, *span)
+            }
+            ExecExpression::Factor(v, _) => ExpressionFlow::Value(v.clone()),
+            ExecExpression::Variable(name, _) => {
                 if let Some(val) = self.variables.get(name) {
-                    ExpressionFlow::Value(*val)
+                    ExpressionFlow::Value(val.clone())
                 } else {
                     panic!("syntax error: unknown variable name")
                 }
             }
-            ExecExpression::If(cond, stats_true, stats_false) => {
-                self.interpret_if(cond, stats_true, stats_false)
+            ExecExpression::GlobalVariable(name, _) => ExpressionFlow::Value(self.global(name)),
+            ExecExpression::ArrayElement(name, source_name, index, span) => {
+                self.interpret_array_element(name, source_name, index, false, *span)
+            }
+            ExecExpression::GlobalArrayElement(name, source_name, index, span) => {
+                self.interpret_array_element(name, source_name, index, true, *span)
             }
-            ExecExpression::While(cond, code) => self.interpret_while(cond, code),
+            ExecExpression::If(branches, stats_else, _) => self.interpret_if(branches, stats_else),
+            ExecExpression::While(cond, code, _) => self.interpret_while(cond, code),
         }
     }
 
     fn interpret_statement(&mut self, statement: &ExecStatement) -> Flow {
         match statement {
-            ExecStatement::Expression(expr) => match self.interpret_expression(expr) {
+            ExecStatement::Expression(expr, _) => match self.interpret_expression(expr) {
                 ExpressionFlow::Value(_) => Flow::Proceed,
                 ExpressionFlow::Jump(j) => j,
             },
-            ExecStatement::Return(expr) => match self.interpret_expression(expr) {
+            ExecStatement::Return(expr, _) => match self.interpret_expression(expr) {
                 ExpressionFlow::Value(res) => Flow::Return(res),
                 ExpressionFlow::Jump(j) => j,
             },
-            ExecStatement::Break => Flow::Break,
-            ExecStatement::Continue => Flow::Continue,
+            ExecStatement::Break(_) => Flow::Break,
+            ExecStatement::Continue(_) => Flow::Continue,
+            ExecStatement::InitializeArray(name, _) => {
+                for x in self.array_mut(name, false).iter_mut() {
+                    *x = Value::from(0);
+                }
+                Flow::Proceed
+            }
         }
     }
 
-    pub fn interpret_statements(&mut self, statements: &Vec<ExecStatement>) -> Flow {
+    pub fn interpret_statements(&mut self, statements: &[ExecStatement]) -> Flow {
         for statement in statements {
             match self.interpret_statement(statement) {
                 Flow::Proceed => (),
@@ -291,13 +721,67 @@ impl LocalEnvironment<'_, '_> {
     }
 }
 
-pub fn interpret_func(env: &mut Environment, scope: &Scope, func_name: &str) -> Option<i64> {
-    let func = scope.get_function(func_name).unwrap();
-    let mut e = LocalEnvironment::new_func(env, scope, &func, &Vec::<i64>::new());
+pub fn interpret_func(
+    env: &mut Environment,
+    scope: &Scope,
+    func_name: &str,
+) -> Result<Option<Value>, RuntimeError> {
+    let func = match scope.get_function(func_name) {
+        Some(x) => x,
+        None => {
+            return Err(RuntimeError {
+                message: format!("unknown function `{}`", func_name),
+                code: code::UNKNOWN_FUNCTION,
+                operation: func_name.to_owned(),
+                code_pointer: None,
+                span: None,
+            })
+        }
+    };
+    if env.globals.is_empty() && env.global_arrays.is_empty() {
+        for v in scope.variables.iter() {
+            if let Some(size) = v.array_size {
+                env.global_arrays
+                    .insert(v.identifier.clone(), vec![Value::from(0); size as usize]);
+            } else {
+                env.globals.insert(v.identifier.clone(), Value::from(0));
+            }
+        }
+        if let Some(init) = &scope.initializer {
+            let mut e = LocalEnvironment::new_func(env, scope, init, vec![]);
+            match e.interpret_statements(&init.code) {
+                // NOTE: 初期化式の return は解析で弾いている
+                Flow::Return(_) => {
+                    return Err(RuntimeError {
+                        message: "internal error: unexpected return in the initializer".to_owned(),
+                        code: code::INTERNAL_ERROR,
+                        operation: String::new(),
+                        code_pointer: None,
+                        span: None,
+                    })
+                }
+                Flow::Error(e) => return Err(e),
+                _ => (),
+            }
+        }
+    }
+    let mut e = LocalEnvironment::new_func(env, scope, func, vec![]);
     let res = e.interpret_statements(&func.code);
-    if let Flow::Return(x) = res {
-        Some(x)
-    } else {
-        None
+    if let Err(e) = env.output.flush() {
+        return Err(RuntimeError {
+            message: e.to_string(),
+            code: code::OUTPUT_ERROR,
+            operation: String::new(),
+            code_pointer: None,
+            span: None,
+        });
+    }
+    match res {
+        Flow::Return(x) => Ok(Some(x)),
+        Flow::Error(e) => Err(e),
+        _ => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,288 @@
+use std::io::{self, Write};
+
+use crate::{
+    base::{Span, Value},
+    diagnostic::code,
+    interpreter::{interpret_func, ArithmeticMode, Environment, RuntimeError},
+    syntactic_analyzer::syntactic_analyze,
+    token_parser::parse_to_tokens,
+    tree_parser::parse_to_tree,
+};
+
+fn run(code: &str, arithmetic: ArithmeticMode) -> Result<Option<Value>, RuntimeError> {
+    run_with_input(code, arithmetic, b"")
+}
+
+fn run_with_input(
+    code: &str,
+    arithmetic: ArithmeticMode,
+    input: &'static [u8],
+) -> Result<Option<Value>, RuntimeError> {
+    let tokens = parse_to_tokens(code).ok().unwrap();
+    let tree = parse_to_tree(&tokens).ok().unwrap();
+    let scope = syntactic_analyze(&tree).ok().unwrap();
+    let mut env = Environment::new(Box::new(input), Box::new(io::sink()));
+    env.arithmetic = arithmetic;
+    interpret_func(&mut env, &scope, "main")
+}
+
+macro_rules! test_runtime_error {
+    ($name: ident, $code: expr, $message: expr, $operation: expr, $ptr: expr) => {
+        #[test]
+        fn $name() {
+            let e = run($code, ArithmeticMode::Checked).err().unwrap();
+            assert_eq!(e.message, $message);
+            assert_eq!(e.operation, $operation);
+            assert_eq!(e.code_pointer, Some($ptr));
+        }
+    };
+}
+
+test_runtime_error!(
+    test_division_by_zero,
+    "func: main() { let: x = 0; return: 7 / x; }",
+    "division by zero",
+    "7 / 0",
+    37
+);
+test_runtime_error!(
+    test_modulo_by_zero,
+    "func: main() { let: x = 7; x %= 0; }",
+    "division by zero",
+    "7 % 0",
+    29
+);
+#[cfg(not(feature = "bigint"))]
+test_runtime_error!(
+    test_overflow_add,
+    "func: main() { return: 9223372036854775807 + 1; }",
+    "arithmetic overflow",
+    "9223372036854775807 + 1",
+    43
+);
+#[cfg(not(feature = "bigint"))]
+test_runtime_error!(
+    test_overflow_negative,
+    "func: main() { let: x = -9223372036854775807 - 1; return: -x; }",
+    "arithmetic overflow",
+    "-(-9223372036854775808)",
+    58
+);
+#[cfg(not(feature = "bigint"))]
+test_runtime_error!(
+    test_overflow_divide,
+    "func: main() { let: x = -9223372036854775807 - 1; return: x / -1; }",
+    "arithmetic overflow",
+    "-9223372036854775808 / -1",
+    60
+);
+#[cfg(not(feature = "bigint"))]
+test_runtime_error!(
+    test_overflow_shift,
+    "func: main() { return: 3 << 62; }",
+    "arithmetic overflow",
+    "3 << 62",
+    25
+);
+test_runtime_error!(
+    test_invalid_shift,
+    "func: main() { return: 1 >> -1; }",
+    "invalid shift amount",
+    "1 >> -1",
+    25
+);
+
+#[test]
+fn test_runtime_error_span() {
+    let code = "func: main() { let: x = 0; return: 7 / x; }";
+    let e = run(code, ArithmeticMode::Checked).err().unwrap();
+    assert_eq!(e.span, Some(Span::new(35, 40)));
+}
+
+test_runtime_error!(
+    test_too_large_shift,
+    "func: main() { let: x = 3; return: (x >> 65536) + (x << 65537); }",
+    "invalid shift amount",
+    "3 << 65537",
+    53
+);
+test_runtime_error!(
+    test_index_out_of_range,
+    "func: main() { let: a[3]; return: a[5]; }",
+    "index out of range",
+    "a[5]",
+    34
+);
+test_runtime_error!(
+    test_index_out_of_range_assign,
+    "let: g[2]; func: main() { g[-1] += 1; }",
+    "index out of range",
+    "g[-1]",
+    26
+);
+test_runtime_error!(
+    test_index_out_of_range_block,
+    "func: main() { if: 0 { let: b[2]; b[2] = 1; }; }",
+    "index out of range",
+    "b[2]",
+    34
+);
+test_runtime_error!(
+    test_invalid_character,
+    "func: main() { __put_char(-1); }",
+    "invalid character",
+    "__put_char(-1)",
+    15
+);
+test_runtime_error!(
+    test_invalid_trace_key,
+    "func: main() { __trace(1); __trace(-2); }",
+    "invalid trace key",
+    "__trace(-2)",
+    27
+);
+test_runtime_error!(
+    test_assertion_failed,
+    "func: main() { __assert_not(1 + 2); }",
+    "assertion failed",
+    "__assert_not(3)",
+    15
+);
+
+#[test]
+fn test_assertion_failed_span() {
+    let code = "func: main() { __assert(0); }";
+    let e = run(code, ArithmeticMode::Checked).err().unwrap();
+    assert_eq!(e.span, Some(Span::new(15, 26)));
+}
+
+#[test]
+fn test_error_propagates_through_calls() {
+    let code = "
+        func: f(x) { return: 1 / x; }
+        func: main() { while: 1 { f(0); }; return: 1; }";
+    let e = run(code, ArithmeticMode::Checked).err().unwrap();
+    assert_eq!(e.operation, "1 / 0");
+}
+
+#[cfg(not(feature = "bigint"))]
+#[test]
+fn test_wrapping() {
+    let code = "func: main() { let: x = 9223372036854775807; return: x + 1; }";
+    assert_eq!(
+        run(code, ArithmeticMode::Wrapping).ok().unwrap(),
+        Some(Value::from(i64::MIN))
+    );
+    let code = "func: main() { let: x = -9223372036854775807 - 1; return: (x / -1 == x) + (3 << 63 == x) + (-x == x); }";
+    assert_eq!(
+        run(code, ArithmeticMode::Wrapping).ok().unwrap(),
+        Some(Value::from(3))
+    );
+    // 0 除算はどちらのモードでもエラー
+    let code = "func: main() { return: 1 % 0; }";
+    assert!(run(code, ArithmeticMode::Wrapping).is_err());
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn test_bigint() {
+    let code = "
+        func: main() {
+            let: x = 1, i = 1;
+            while: i - 31 { x *= i; i += 1; };
+            __assert_not(x - 265252859812191058636308480000000);
+            __assert_not((x >> 100) - 209);
+            __assert_not((-x / 1000000000000000000000000000000) + 266);
+            return: x % 1000000007;
+        }";
+    assert_eq!(
+        run(code, ArithmeticMode::Checked).ok().unwrap(),
+        Some(Value::from(109361473))
+    );
+}
+
+#[test]
+fn test_unknown_function() {
+    let tokens = parse_to_tokens("func: main() { return: 1; }").ok().unwrap();
+    let tree = parse_to_tree(&tokens).ok().unwrap();
+    let scope = syntactic_analyze(&tree).ok().unwrap();
+    let mut env = Environment::new(Box::new(io::empty()), Box::new(io::sink()));
+    let e = interpret_func(&mut env, &scope, "f").err().unwrap();
+    assert_eq!(e.code, code::UNKNOWN_FUNCTION);
+    assert_eq!(e.code_pointer, None);
+}
+
+#[test]
+fn test_invalid_input() {
+    let code = "func: main() { return: __read_int() + __read_int(); }";
+    assert_eq!(
+        run_with_input(code, ArithmeticMode::Checked, b"3\n-4\n")
+            .ok()
+            .unwrap(),
+        Some(Value::from(-1))
+    );
+    let e = run_with_input(code, ArithmeticMode::Checked, b"3\n")
+        .err()
+        .unwrap();
+    assert_eq!(e.code, code::INVALID_INPUT);
+    assert_eq!(e.message, "unexpected end of input");
+    assert_eq!(e.operation, "__read_int()");
+    assert_eq!(e.span, Some(Span::new(38, 50)));
+    let e = run_with_input(code, ArithmeticMode::Checked, b"x\n")
+        .err()
+        .unwrap();
+    assert_eq!(e.message, "invalid number: x");
+    assert_eq!(e.span, Some(Span::new(23, 35)));
+
+    let code = "func: main() { return: __read_char(); }";
+    assert_eq!(
+        run_with_input(code, ArithmeticMode::Checked, b"")
+            .ok()
+            .unwrap(),
+        Some(Value::from(-1))
+    );
+    let e = run_with_input(code, ArithmeticMode::Checked, b"\xff")
+        .err()
+        .unwrap();
+    assert_eq!(e.code, code::INVALID_INPUT);
+    assert_eq!(e.operation, "__read_char()");
+}
+
+// 書き込みに必ず失敗する出力
+struct BrokenOutput;
+
+impl Write for BrokenOutput {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_output_error() {
+    let code = "func: main() { __trace(0); __put_int(12); __trace(1); return: 0; }";
+    let tokens = parse_to_tokens(code).ok().unwrap();
+    let tree = parse_to_tree(&tokens).ok().unwrap();
+    let scope = syntactic_analyze(&tree).ok().unwrap();
+    let mut env = Environment::new(Box::new(io::empty()), Box::new(BrokenOutput));
+    let e = interpret_func(&mut env, &scope, "main").err().unwrap();
+    assert_eq!(e.code, code::OUTPUT_ERROR);
+    assert_eq!(e.message, "broken pipe");
+    assert_eq!(e.operation, "__put_int(12)");
+    assert_eq!(e.span, Some(Span::new(27, 40)));
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn test_bigint_input() {
+    let code = "func: main() { return: __read_int() * 2; }";
+    assert_eq!(
+        run_with_input(code, ArithmeticMode::Checked, b"-18446744073709551616\n")
+            .ok()
+            .unwrap(),
+        Value::parse("-36893488147419103232")
+    );
+}
@@ -2,47 +2,109 @@
 #[macro_use]
 extern crate assert_matches;
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, io};
 
-pub use base::CodeParseError;
+pub use base::{CodeParseError, Span, Value};
+pub use diagnostic::{code as error_code, Diagnostic, Label, Severity};
 use interpreter::Environment;
+pub use interpreter::{ArithmeticMode, RuntimeError};
 pub use logger::TextCode;
 use syntactic_analyzer::Scope;
 use token_parser::PrettyToken;
 use tree_parser::Statement;
+use whitespace::VirtualMachine;
 
 mod base;
+mod compiler;
+mod diagnostic;
 mod interpreter;
 mod logger;
 mod syntactic_analyzer;
 mod token_parser;
 mod tree_parser;
+mod whitespace;
 
-pub fn parse_to_tokens(text: &String) -> Result<Vec<PrettyToken>, Vec<CodeParseError>> {
+pub fn parse_to_tokens(text: &str) -> Result<Vec<PrettyToken>, Vec<CodeParseError>> {
     match token_parser::parse_to_tokens(text) {
         Ok(x) => Ok(x),
         Err(err) => Err(err.iter().map(|e| e.shrink()).collect()),
     }
 }
 
-pub fn parse_to_tree(tokens: &Vec<PrettyToken>) -> Result<Vec<Statement>, Vec<CodeParseError>> {
+pub fn parse_to_tree(tokens: &[PrettyToken]) -> Result<Vec<Statement>, Vec<CodeParseError>> {
     match tree_parser::parse_to_tree(tokens) {
         Ok(x) => Ok(x),
         Err(err) => Err(err.iter().map(|e| e.shrink()).collect()),
     }
 }
 
-pub fn syntactic_analyze(root: &Vec<Statement>) -> Scope {
-    syntactic_analyzer::syntactic_analyze(root)
+pub fn syntactic_analyze(root: &[Statement]) -> Result<Scope, Vec<CodeParseError>> {
+    match syntactic_analyzer::syntactic_analyze(root) {
+        Ok(x) => Ok(x),
+        Err(err) => Err(err.iter().map(|e| e.shrink()).collect()),
+    }
+}
+
+pub fn compile_to_whitespace(scope: &Scope) -> String {
+    whitespace::encode(&compiler::compile(scope))
+}
+
+pub fn compile_to_assembly(scope: &Scope) -> String {
+    compiler::asm::emit(&compiler::compile(scope))
 }
 
-pub fn interpret_func(scope: &Scope, func_name: &str) -> Option<i64> {
-    let mut env = Environment::new();
+// convert whitespace assembly to whitespace code.
+pub fn assemble(text: &str) -> Result<String, Vec<CodeParseError>> {
+    match compiler::asm::parse(text) {
+        Ok(x) => Ok(whitespace::encode(&x)),
+        Err(err) => Err(err.iter().map(|e| e.shrink()).collect()),
+    }
+}
+
+// `__read_int`, `__read_char` read from stdin. `__put_int`, `__put_char` write to stdout.
+pub fn interpret_func(
+    scope: &Scope,
+    func_name: &str,
+    arithmetic: ArithmeticMode,
+) -> Result<Option<Value>, RuntimeError> {
+    let mut env = Environment::new(
+        Box::new(io::BufReader::new(io::stdin())),
+        Box::new(io::stdout()),
+    );
+    env.arithmetic = arithmetic;
     interpreter::interpret_func(&mut env, scope, func_name)
 }
 
-pub fn interpret_func_testing(scope: &Scope, func_name: &str) -> BTreeMap<i64, i64> {
-    let mut env = Environment::new();
-    interpreter::interpret_func(&mut env, scope, func_name);
-    env.traced
+// return the recorded traces, the returned value and the output.
+pub fn interpret_func_testing(
+    scope: &Scope,
+    func_name: &str,
+    input: &str,
+) -> (BTreeMap<i64, i64>, Option<Value>, Vec<u8>) {
+    let mut output = Vec::<u8>::new();
+    let mut env = Environment::new(Box::new(io::Cursor::new(input)), Box::new(&mut output));
+    let res = interpreter::interpret_func(&mut env, scope, func_name)
+        .unwrap_or_else(|e| panic!("runtime error: {}", e));
+    let traced = std::mem::take(&mut env.traced);
+    drop(env);
+    (traced, res, output)
+}
+
+// run whitespace code and return the recorded traces, the value left on the stack and the output.
+pub fn run_whitespace_testing(
+    code: &str,
+    input: &str,
+) -> (BTreeMap<i64, i64>, Option<Value>, Vec<u8>) {
+    let instructions = match whitespace::parse(code) {
+        Ok(x) => x,
+        Err(err) => panic!("invalid whitespace code: {}", err[0].message),
+    };
+    let mut vm = VirtualMachine::new(&instructions).unwrap();
+    let mut output = Vec::<u8>::new();
+    vm.run(&mut io::Cursor::new(input), &mut output).unwrap();
+    (
+        compiler::collect_traced(&vm.heap),
+        vm.stack.last().cloned(),
+        output,
+    )
 }
@@ -1,10 +1,10 @@
 pub struct TextCode<'a> {
-    // source: &'a str,
+    source: &'a str,
     lines: Vec<&'a str>,
     line_indices: Vec<usize>,
+    len: usize, // the number of chars
 }
 
-// TODO: add a function that convert from char based index to byte based index.
 impl<'a> TextCode<'a> {
     pub fn new(source: &'a str) -> Self {
         // NOTE: do not use lines().
@@ -22,9 +22,10 @@ impl<'a> TextCode<'a> {
             .collect();
         // TODO: indexize
         Self {
-            // source,
+            source,
             lines,
             line_indices,
+            len: source.chars().count(),
         }
     }
 
@@ -38,6 +39,19 @@ impl<'a> TextCode<'a> {
         }
     }
 
+    // i が (末尾も含めて) ソースコードの中を指しているか
+    pub fn contains(&self, i: usize) -> bool {
+        !self.lines.is_empty() && i <= self.len
+    }
+
+    // char 単位の位置を byte 単位の位置に変換する。末尾より後ろなら末尾
+    pub fn char_index_to_byte(&self, i: usize) -> usize {
+        self.source
+            .char_indices()
+            .nth(i)
+            .map_or(self.source.len(), |(b, _)| b)
+    }
+
     // (line, column)
     pub fn char_index_to_line(&self, i: usize) -> (usize, usize) {
         match self.line_indices.binary_search(&i) {
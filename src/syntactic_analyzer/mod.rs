@@ -1,10 +1,16 @@
 use std::collections::BTreeMap;
 
-use crate::tree_parser::{Expression, Operator1, Operator2, Statement};
+use crate::{
+    base::{CodeParseErrorInternal, Span, Value},
+    code_parse_error,
+    diagnostic::code,
+    tree_parser::{Expression, Operator1, Operator2, Statement},
+};
 
 struct IdentifierInfo {
     // name: String,
     idx: usize, // TODO: more safety
+    span: Span, // 宣言した名前の位置
 }
 
 enum Identifier {
@@ -14,56 +20,43 @@ enum Identifier {
 
 pub struct Variable {
     // NOTE: ここに初期化情報は置かない
-    pub identifier: String, // TODO: use IdentifierInfo
+    pub identifier: String,      // TODO: use IdentifierInfo
+    pub array_size: Option<i64>, // 配列なら要素数
 }
 
+// NOTE: 末尾の Span は元になった式・文の範囲
 // #[derive(Clone)] // TODO: REMOVE
 pub enum ExecExpression {
-    Operation1(Operator1, Box<ExecExpression>),
-    Operation2(Operator2, Box<ExecExpression>, Box<ExecExpression>),
-    If(Box<ExecExpression>, Vec<ExecStatement>, Vec<ExecStatement>),
-    While(Box<ExecExpression>, Vec<ExecStatement>),
-    Function(String, Vec<Box<ExecExpression>>),
-    Factor(i64),
-    Variable(String),
+    Operation1(Operator1, Box<ExecExpression>, usize, Span), // usize: code_pointer of the operator
+    Operation2(
+        Operator2,
+        Box<ExecExpression>,
+        Box<ExecExpression>,
+        usize,
+        Span,
+    ), // usize: code_pointer of the operator
+    If(
+        Vec<(Box<ExecExpression>, Vec<ExecStatement>)>, // (cond, block) の列。cond が 0 の分岐を実行する
+        Vec<ExecStatement>,                             // else
+        Span,
+    ),
+    While(Box<ExecExpression>, Vec<ExecStatement>, Span),
+    Function(String, Vec<ExecExpression>, Span),
+    Factor(Value, Span),
+    Variable(String, Span),
+    GlobalVariable(String, Span),
+    // (identifier, ソース上の名前, index)。ソース上の名前は実行時エラーの表示に使う
+    ArrayElement(String, String, Box<ExecExpression>, Span),
+    GlobalArrayElement(String, String, Box<ExecExpression>, Span),
 }
 
 // #[derive(Clone)] // TODO: REMOVE
 pub enum ExecStatement {
-    Return(Box<ExecExpression>),
-    Break,
-    Continue,
-    Expression(Box<ExecExpression>),
-}
-
-fn convert_to_exec_expression(expr: &Box<Expression>) -> Box<ExecExpression> {
-    match expr.as_ref() {
-        Expression::Operation1(op, x) => Box::new(ExecExpression::Operation1(
-            op.to_owned(),
-            convert_to_exec_expression(&x),
-        )),
-        Expression::Operation2(op, l, r) => Box::new(ExecExpression::Operation2(
-            op.to_owned(),
-            convert_to_exec_expression(&l),
-            convert_to_exec_expression(&r),
-        )),
-        Expression::If(cond, stat1, stat2) => Box::new(ExecExpression::If(
-            convert_to_exec_expression(cond),
-            syntactic_analyze_internal(stat1, ScopeType::Block).1,
-            syntactic_analyze_internal(stat2, ScopeType::Block).1,
-        )),
-        Expression::While(expr, stat) => Box::new(ExecExpression::While(
-            convert_to_exec_expression(expr),
-            syntactic_analyze_internal(stat, ScopeType::Block).1,
-        )),
-        Expression::Function(f, a) => Box::new(ExecExpression::Function(
-            f.to_owned(),
-            a.iter().map(|e| convert_to_exec_expression(e)).collect(),
-        )),
-        Expression::Factor(v) => Box::new(ExecExpression::Factor(v.to_owned())),
-        Expression::Variable(v) => Box::new(ExecExpression::Variable(v.to_owned())),
-        Expression::Invalid(_) => todo!(),
-    }
+    Return(Box<ExecExpression>, Span),
+    Break(Span),
+    Continue(Span),
+    Expression(Box<ExecExpression>, Span),
+    InitializeArray(String, Span), // 全ての要素を 0 にする
 }
 
 pub struct Function {
@@ -77,6 +70,8 @@ pub struct Scope {
     identifier_map: BTreeMap<String, Identifier>,
     pub variables: Vec<Variable>,
     functions: Vec<Function>,
+    // root のみ。大域変数の初期化式を順に評価する、引数の無い関数
+    pub initializer: Option<Box<Function>>,
 }
 
 impl Scope {
@@ -88,6 +83,15 @@ impl Scope {
         }
     }
 
+    pub fn functions(&self) -> impl Iterator<Item = (&String, &Function)> {
+        self.identifier_map
+            .iter()
+            .filter_map(move |(name, identifier)| match identifier {
+                Identifier::Function(info) => Some((name, &self.functions[info.idx])),
+                _ => None,
+            })
+    }
+
     pub fn get_variable(&self, id: &str) -> Option<&Variable> {
         if let Some(Identifier::Variable(info)) = self.identifier_map.get(id) {
             Some(&self.variables[info.idx])
@@ -123,105 +127,557 @@ impl ScopeBuilder {
             identifier_map: self.identifier_map,
             variables: self.variables,
             functions: self.functions,
+            initializer: None,
         }
     }
 
-    fn add_identifier(&mut self, name: String, identifier: Identifier) {
-        if self.identifier_map.contains_key(&name) {
-            panic!("syntactic error: the name is already used");
+    // 名前が既に使われていれば、最初に宣言された位置を返す
+    fn add_identifier(&mut self, name: String, identifier: Identifier) -> Result<(), Span> {
+        match self.identifier_map.get(&name) {
+            Some(Identifier::Function(info)) | Some(Identifier::Variable(info)) => Err(info.span),
+            None => {
+                self.identifier_map.insert(name, identifier);
+                Ok(())
+            }
         }
-        self.identifier_map.insert(name, identifier);
     }
 
-    fn add_variable(&mut self, name: String, var: Variable) {
+    fn add_variable(&mut self, name: String, var: Variable, span: Span) -> Result<(), Span> {
         let vi = self.variables.len();
+        self.add_identifier(name, Identifier::Variable(IdentifierInfo { idx: vi, span }))?;
         self.variables.push(var);
-        self.add_identifier(name, Identifier::Variable(IdentifierInfo { idx: vi }));
+        Ok(())
     }
 
-    fn add_function(&mut self, name: String, func: Function) {
+    fn add_function(&mut self, name: String, func: Function, span: Span) -> Result<(), Span> {
         let fi = self.functions.len();
+        self.add_identifier(name, Identifier::Function(IdentifierInfo { idx: fi, span }))?;
         self.functions.push(func);
-        self.add_identifier(name, Identifier::Function(IdentifierInfo { idx: fi }));
+        Ok(())
     }
 }
 
-fn syntactic_analyze_internal(
-    statements: &Vec<Statement>,
-    scope_type: ScopeType,
-) -> (ScopeBuilder, Vec<ExecStatement>) {
-    let mut scope = ScopeBuilder::new();
-    let mut exec_statements = Vec::<ExecStatement>::new();
-    for stat in statements {
-        match stat {
-            Statement::VariableDeclaration(name, init) => {
-                if let ScopeType::Block = scope_type {
-                    panic!("todo: block scoped variable is not implemented")
-                }
-                if let ScopeType::Root = scope_type {
-                    panic!("todo: global variable is not implemented")
-                }
-                scope.add_variable(
-                    name.clone(),
+// (name, the number of arguments)
+const BUILTIN_FUNCTIONS: &[(&str, usize)] = &[
+    ("__clog", 1),
+    ("__assert", 1),
+    ("__assert_not", 1),
+    ("__trace", 1),
+    ("__read_int", 0),
+    ("__read_char", 0),
+    ("__put_int", 1),
+    ("__put_char", 1),
+    ("__puts", 1),
+];
+
+struct SyntacticAnalyzer {
+    code_parse_error: Vec<CodeParseErrorInternal>,
+    loop_depth: usize,
+    in_function: bool,                  // 大域変数の初期化式の中は false
+    functions: BTreeMap<String, usize>, // name -> the number of arguments
+    globals: BTreeMap<String, bool>,    // name -> is_array
+    // 現在の関数で参照可能な変数。ブロック毎に積む (name -> (identifier, is_array))
+    variable_scopes: Vec<BTreeMap<String, (String, bool)>>,
+    // ブロック内で宣言された変数。関数の scope にまとめて置く
+    block_variables: Vec<(Variable, Span)>,
+}
+
+impl SyntacticAnalyzer {
+    fn add_error(&mut self, span: Span, code: u16, msg: String) {
+        self.code_parse_error.push(
+            code_parse_error!(span.begin, msg)
+                .with_code(code)
+                .with_span(span),
+        );
+    }
+
+    fn add_duplicated_name_error(&mut self, span: Span, name: &str, first: Span) {
+        self.code_parse_error.push(
+            code_parse_error!(span.begin, format!("the name `{}` is already used", name))
+                .with_code(code::DUPLICATED_NAME)
+                .with_span(span)
+                .with_label(first, format!("`{}` first declared here", name)),
+        );
+    }
+
+    // 変数を探し、(identifier, is_global) を返す
+    fn resolve_variable(
+        &mut self,
+        name: &str,
+        is_array: bool,
+        span: Span,
+    ) -> Option<(String, bool)> {
+        let found = match self
+            .variable_scopes
+            .iter()
+            .rev()
+            .find_map(|variables| variables.get(name))
+        {
+            Some((identifier, a)) => Some((identifier.clone(), false, *a)),
+            None => self.globals.get(name).map(|a| (name.to_owned(), true, *a)),
+        };
+        match found {
+            None => {
+                self.add_error(
+                    span,
+                    code::UNKNOWN_VARIABLE,
+                    format!("unknown variable `{}`", name),
+                );
+                None
+            }
+            Some((_, _, a)) if a != is_array => {
+                let msg = if a {
+                    format!("array `{}` must be indexed", name)
+                } else {
+                    format!("variable `{}` is not an array", name)
+                };
+                self.add_error(span, code::ARRAY_MISMATCH, msg);
+                None
+            }
+            Some((identifier, is_global, _)) => Some((identifier, is_global)),
+        }
+    }
+
+    fn declare_variable(&mut self, name: &str, identifier: &str, is_array: bool) {
+        self.variable_scopes
+            .last_mut()
+            .expect("internal error: no scope")
+            .insert(name.to_owned(), (identifier.to_owned(), is_array));
+    }
+
+    // 変数を scope に登録し、identifier を返す。登録できなければ None
+    fn register_variable(
+        &mut self,
+        scope_type: &ScopeType,
+        scope: &mut ScopeBuilder,
+        name: &str,
+        array_size: Option<i64>,
+        span: Span, // 宣言した名前の位置
+    ) -> Option<String> {
+        // ブロック変数は別の変数と区別できる identifier を付ける
+        let identifier = match scope_type {
+            ScopeType::Block => format!("{}.{}", name, self.block_variables.len()),
+            _ => name.to_owned(),
+        };
+        if let Err(first) = scope.add_variable(
+            name.to_owned(),
+            Variable {
+                identifier: identifier.clone(),
+                array_size,
+            },
+            span,
+        ) {
+            self.add_duplicated_name_error(span, name, first);
+            return None;
+        }
+        match scope_type {
+            ScopeType::Root => (), // 大域変数は self.globals から引く
+            ScopeType::Function => self.declare_variable(name, &identifier, array_size.is_some()),
+            ScopeType::Block => {
+                self.declare_variable(name, &identifier, array_size.is_some());
+                self.block_variables.push((
                     Variable {
-                        identifier: name.clone(),
+                        identifier: identifier.clone(),
+                        array_size,
                     },
-                );
-                exec_statements.push(ExecStatement::Expression(convert_to_exec_expression(init)));
+                    span,
+                ));
             }
-            Statement::FunctionDeclaration(name, args, block) => {
-                if let ScopeType::Block = scope_type {
-                    panic!("syntactic error: invalid return in block")
+        }
+        Some(identifier)
+    }
+
+    fn analyze_block(&mut self, statements: &[Statement]) -> Vec<ExecStatement> {
+        self.variable_scopes.push(BTreeMap::new());
+        let code = self.analyze_statements(statements, ScopeType::Block, &mut ScopeBuilder::new());
+        self.variable_scopes.pop();
+        code
+    }
+
+    fn validate_function_call(&mut self, name: &str, argc: usize, span: Span) {
+        let expected = match BUILTIN_FUNCTIONS.iter().find(|(n, _)| *n == name) {
+            Some((_, n)) => *n,
+            None => match self.functions.get(name) {
+                Some(n) => *n,
+                None => {
+                    self.add_error(
+                        span,
+                        code::UNKNOWN_FUNCTION,
+                        format!("unknown function `{}`", name),
+                    );
+                    return;
                 }
-                let (mut s, es) = syntactic_analyze_internal(block, ScopeType::Function);
-                // add variable definition to scope
-                for a in args {
-                    s.add_variable(
-                        a.clone(),
-                        Variable {
-                            identifier: a.clone(),
-                        },
+            },
+        };
+        if expected != argc {
+            self.add_error(
+                span,
+                code::ARGUMENT_COUNT,
+                format!(
+                    "function `{}` takes {} argument(s) but {} were given",
+                    name, expected, argc
+                ),
+            );
+        }
+    }
+
+    fn convert_to_exec_expression(&mut self, expr: &Expression) -> Box<ExecExpression> {
+        match expr {
+            Expression::Operation1(op, x, code_pointer, span) => {
+                Box::new(ExecExpression::Operation1(
+                    op.to_owned(),
+                    self.convert_to_exec_expression(x),
+                    *code_pointer,
+                    *span,
+                ))
+            }
+            Expression::Operation2(op, l, r, code_pointer, span) => {
+                // 代入先は変数か配列の要素に限る
+                if op.is_assign()
+                    && !matches!(**l, Expression::Variable(..) | Expression::ArrayElement(..))
+                {
+                    self.add_error(
+                        l.span().unwrap_or(*span),
+                        code::INVALID_ASSIGNMENT,
+                        format!("invalid left-hand side of `{}`", op),
                     );
                 }
-                // store variable identifier to function
-                let func = Function {
-                    args: args.clone(),
-                    scope: s.build(),
-                    code: es,
+                Box::new(ExecExpression::Operation2(
+                    op.to_owned(),
+                    self.convert_to_exec_expression(l),
+                    self.convert_to_exec_expression(r),
+                    *code_pointer,
+                    *span,
+                ))
+            }
+            Expression::If(branches, stats_else, span) => Box::new(ExecExpression::If(
+                branches
+                    .iter()
+                    .map(|(cond, stats)| {
+                        (
+                            self.convert_to_exec_expression(cond),
+                            self.analyze_block(stats),
+                        )
+                    })
+                    .collect(),
+                self.analyze_block(stats_else),
+                *span,
+            )),
+            Expression::While(expr, stat, span) => {
+                let cond = self.convert_to_exec_expression(expr);
+                self.loop_depth += 1;
+                let code = self.analyze_block(stat);
+                self.loop_depth -= 1;
+                Box::new(ExecExpression::While(cond, code, *span))
+            }
+            Expression::Function(f, a, span) if f == "__puts" => {
+                self.validate_function_call(f, a.len(), name_span(*span, f));
+                // 文字列は値として扱えないので、文字の列に展開する
+                let (text, text_span) = match a.first() {
+                    Some(Expression::String(text, text_span)) => (text.clone(), *text_span),
+                    Some(e) => {
+                        let e = self.convert_to_exec_expression(e);
+                        self.add_error(
+                            *span,
+                            code::INVALID_STRING_LITERAL,
+                            "`__puts` takes a string literal".to_owned(),
+                        );
+                        return Box::new(ExecExpression::Function(f.to_owned(), vec![*e], *span));
+                    }
+                    None => (String::new(), *span),
                 };
-                scope.add_function(name.clone(), func);
+                Box::new(ExecExpression::Function(
+                    f.to_owned(),
+                    text.chars()
+                        .map(|c| ExecExpression::Factor(Value::from(c as i64), text_span))
+                        .collect(),
+                    *span,
+                ))
             }
-            Statement::Return(e) => {
-                if let ScopeType::Root = scope_type {
-                    panic!("syntactic error: invalid return in root")
-                }
-                exec_statements.push(ExecStatement::Return(convert_to_exec_expression(e)));
+            Expression::Function(f, a, span) => {
+                self.validate_function_call(f, a.len(), name_span(*span, f));
+                Box::new(ExecExpression::Function(
+                    f.to_owned(),
+                    a.iter()
+                        .map(|e| *self.convert_to_exec_expression(e))
+                        .collect(),
+                    *span,
+                ))
             }
-            Statement::Expression(e) => {
-                if let ScopeType::Root = scope_type {
-                    panic!("syntactic error: invalid expression in root")
-                }
-                exec_statements.push(ExecStatement::Expression(convert_to_exec_expression(e)));
+            Expression::Factor(v, span) => Box::new(ExecExpression::Factor(v.to_owned(), *span)),
+            Expression::String(_, span) => {
+                self.add_error(
+                    *span,
+                    code::INVALID_STRING_LITERAL,
+                    "string literal is only allowed in `__puts`".to_owned(),
+                );
+                Box::new(ExecExpression::Factor(Value::from(0), *span))
             }
-            Statement::Continue => {
-                if let ScopeType::Root = scope_type {
-                    panic!("syntactic error: invalid return in root")
+            Expression::Variable(v, span) => match self.resolve_variable(v, false, *span) {
+                Some((identifier, true)) => {
+                    Box::new(ExecExpression::GlobalVariable(identifier, *span))
+                }
+                Some((identifier, false)) => Box::new(ExecExpression::Variable(identifier, *span)),
+                None => Box::new(ExecExpression::Variable(v.to_owned(), *span)), // error
+            },
+            Expression::ArrayElement(v, index, span) => {
+                let resolved = self.resolve_variable(v, true, *span);
+                let index = self.convert_to_exec_expression(index);
+                match resolved {
+                    Some((identifier, true)) => Box::new(ExecExpression::GlobalArrayElement(
+                        identifier,
+                        v.to_owned(),
+                        index,
+                        *span,
+                    )),
+                    Some((identifier, false)) => Box::new(ExecExpression::ArrayElement(
+                        identifier,
+                        v.to_owned(),
+                        index,
+                        *span,
+                    )),
+                    None => Box::new(ExecExpression::ArrayElement(
+                        v.to_owned(),
+                        v.to_owned(),
+                        index,
+                        *span,
+                    )), // error
                 }
-                exec_statements.push(ExecStatement::Continue);
             }
-            Statement::Break => {
-                if let ScopeType::Root = scope_type {
-                    panic!("syntactic error: invalid return in root")
+            Expression::Invalid(_) => unreachable!("the tree has syntax errors"),
+        }
+    }
+
+    // span: 関数名の位置
+    fn analyze_function(&mut self, args: &[(String, Span)], block: &[Statement]) -> Function {
+        let loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+        self.in_function = true;
+        // NOTE: 大域変数の初期化式で宣言されたブロック変数を退避する
+        let outer_block_variables = std::mem::take(&mut self.block_variables);
+        self.variable_scopes = vec![BTreeMap::new()];
+        let mut s = ScopeBuilder::new();
+        // add variable definition to scope
+        for (a, span) in args {
+            self.register_variable(&ScopeType::Function, &mut s, a, None, *span);
+        }
+        let es = self.analyze_statements(block, ScopeType::Function, &mut s);
+        self.loop_depth = loop_depth;
+        self.in_function = false;
+        self.variable_scopes.clear();
+        self.add_block_variables(&mut s);
+        self.block_variables = outer_block_variables;
+        // store variable identifier to function
+        Function {
+            args: args.iter().map(|(a, _)| a.clone()).collect(),
+            scope: s.build(),
+            code: es,
+        }
+    }
+
+    fn add_block_variables(&mut self, scope: &mut ScopeBuilder) {
+        for (v, span) in std::mem::take(&mut self.block_variables) {
+            // NOTE: ブロック変数の identifier は `.` を含むので衝突しない
+            scope
+                .add_variable(v.identifier.clone(), v, span)
+                .expect("internal error: block variable conflicts");
+        }
+    }
+
+    fn analyze_statements(
+        &mut self,
+        statements: &[Statement],
+        scope_type: ScopeType,
+        scope: &mut ScopeBuilder,
+    ) -> Vec<ExecStatement> {
+        let mut exec_statements = Vec::<ExecStatement>::new();
+        for stat in statements {
+            match stat {
+                Statement::VariableDeclaration(name, init, span) => {
+                    // NOTE: 初期化式の中では、まだ宣言した変数は見えない
+                    let e = self.convert_to_exec_expression(init);
+                    let target_span = name_span(*span, name);
+                    let identifier =
+                        match self.register_variable(&scope_type, scope, name, None, target_span) {
+                            Some(x) => x,
+                            None => continue,
+                        };
+                    // 大域変数は 0 で初期化された後、宣言順に初期化式を評価する
+                    // ブロック変数はブロックに入る度に初期化し直す
+                    let target = match scope_type {
+                        ScopeType::Root => ExecExpression::GlobalVariable(identifier, target_span),
+                        _ => ExecExpression::Variable(identifier, target_span),
+                    };
+                    exec_statements.push(ExecStatement::Expression(
+                        Box::new(ExecExpression::Operation2(
+                            Operator2::Assign,
+                            Box::new(target),
+                            e,
+                            span.begin,
+                            *span,
+                        )),
+                        *span,
+                    ));
+                }
+                Statement::ArrayDeclaration(name, size, span) => {
+                    let identifier = match self.register_variable(
+                        &scope_type,
+                        scope,
+                        name,
+                        Some(*size),
+                        name_span(*span, name),
+                    ) {
+                        Some(x) => x,
+                        None => continue,
+                    };
+                    // NOTE: 大域変数の配列は最初から 0
+                    if !matches!(scope_type, ScopeType::Root) {
+                        exec_statements.push(ExecStatement::InitializeArray(identifier, *span));
+                    }
+                }
+                Statement::FunctionDeclaration(name, args, block, span) => {
+                    let span = name_span(*span, name);
+                    if !matches!(scope_type, ScopeType::Root) {
+                        self.add_error(
+                            span,
+                            code::MISPLACED_STATEMENT,
+                            "function must be declared in root".to_owned(),
+                        );
+                        continue;
+                    }
+                    let func = self.analyze_function(args, block);
+                    if let Err(first) = scope.add_function(name.clone(), func, span) {
+                        self.add_duplicated_name_error(span, name, first);
+                    }
+                }
+                Statement::Return(e, span) => {
+                    // NOTE: 初期化式のブロックの中も root とみなす
+                    if !self.in_function {
+                        self.add_error(
+                            *span,
+                            code::MISPLACED_STATEMENT,
+                            "invalid return in root".to_owned(),
+                        );
+                        continue;
+                    }
+                    let e = self.convert_to_exec_expression(e);
+                    exec_statements.push(ExecStatement::Return(e, *span));
                 }
-                exec_statements.push(ExecStatement::Break);
+                Statement::Expression(e, span) => {
+                    if let ScopeType::Root = scope_type {
+                        self.add_error(
+                            *span,
+                            code::MISPLACED_STATEMENT,
+                            "invalid expression in root".to_owned(),
+                        );
+                        continue;
+                    }
+                    let e = self.convert_to_exec_expression(e);
+                    exec_statements.push(ExecStatement::Expression(e, *span));
+                }
+                Statement::Continue(span) => {
+                    if self.loop_depth == 0 {
+                        self.add_error(
+                            *span,
+                            code::OUTSIDE_OF_LOOP,
+                            "continue outside of loop".to_owned(),
+                        );
+                        continue;
+                    }
+                    exec_statements.push(ExecStatement::Continue(*span));
+                }
+                Statement::Break(span) => {
+                    if self.loop_depth == 0 {
+                        self.add_error(
+                            *span,
+                            code::OUTSIDE_OF_LOOP,
+                            "break outside of loop".to_owned(),
+                        );
+                        continue;
+                    }
+                    exec_statements.push(ExecStatement::Break(*span));
+                }
+                Statement::Invalid(_) => (),
+            }
+        }
+        exec_statements
+    }
+
+    // プログラムは引数の無い main から実行する
+    fn check_main(&mut self, root: &[Statement]) {
+        let main = root.iter().find_map(|stat| match stat {
+            Statement::FunctionDeclaration(name, args, _, span) if name == "main" => {
+                Some((args, *span))
             }
-            Statement::Invalid(_) => (),
+            _ => None,
+        });
+        match main {
+            None => self.code_parse_error.push(
+                code_parse_error!("function `main` is not found".to_owned())
+                    .with_code(code::INVALID_MAIN),
+            ),
+            Some((args, span)) if !args.is_empty() => self.add_error(
+                name_span(span, "main"),
+                code::INVALID_MAIN,
+                "function `main` must not take arguments".to_owned(),
+            ),
+            _ => (),
         }
     }
-    (scope, exec_statements)
 }
 
-pub fn syntactic_analyze(root: &Vec<Statement>) -> Scope {
-    syntactic_analyze_internal(root, ScopeType::Root).0.build()
-    // TODO: validate identifiers
+// 宣言・呼び出しの範囲 span のうち、先頭の名前の部分
+fn name_span(span: Span, name: &str) -> Span {
+    Span::new(span.begin, span.begin + name.chars().count())
 }
+
+pub fn syntactic_analyze(root: &[Statement]) -> Result<Scope, Vec<CodeParseErrorInternal>> {
+    let mut analyzer = SyntacticAnalyzer {
+        code_parse_error: vec![],
+        loop_depth: 0,
+        in_function: false,
+        functions: BTreeMap::new(),
+        globals: BTreeMap::new(),
+        variable_scopes: vec![],
+        block_variables: vec![],
+    };
+    // 関数と大域変数は宣言より前で参照できるので、先に一覧を作る
+    for stat in root {
+        match stat {
+            Statement::FunctionDeclaration(name, args, _, _) => {
+                analyzer
+                    .functions
+                    .entry(name.clone())
+                    .or_insert_with(|| args.len());
+            }
+            Statement::VariableDeclaration(name, _, _) => {
+                analyzer.globals.insert(name.clone(), false);
+            }
+            Statement::ArrayDeclaration(name, _, _) => {
+                analyzer.globals.insert(name.clone(), true);
+            }
+            _ => (),
+        }
+    }
+    let mut scope = ScopeBuilder::new();
+    let code = analyzer.analyze_statements(root, ScopeType::Root, &mut scope);
+    analyzer.check_main(root);
+    let mut initializer_scope = ScopeBuilder::new();
+    analyzer.add_block_variables(&mut initializer_scope);
+    let mut scope = scope.build();
+    if !code.is_empty() {
+        scope.initializer = Some(Box::new(Function {
+            args: vec![],
+            scope: initializer_scope.build(),
+            code,
+        }));
+    }
+    if analyzer.code_parse_error.is_empty() {
+        Ok(scope)
+    } else {
+        Err(analyzer.code_parse_error)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,238 @@
+use crate::{
+    base::{CodeParseErrorInternal, Span},
+    diagnostic::code,
+    syntactic_analyzer::syntactic_analyze,
+    token_parser::parse_to_tokens,
+    tree_parser::parse_to_tree,
+};
+
+fn analyze_program_errors(code: &str) -> Vec<CodeParseErrorInternal> {
+    let tokens = parse_to_tokens(code).ok().unwrap();
+    let tree = parse_to_tree(&tokens).ok().unwrap();
+    match syntactic_analyze(&tree) {
+        Ok(_) => vec![],
+        Err(e) => e,
+    }
+}
+
+// main の検査とは関係の無いテストのために、末尾に main を補う
+fn analyze_errors(code: &str) -> Vec<CodeParseErrorInternal> {
+    analyze_program_errors(&format!("{} func: main() {{ return: 0; }}", code))
+}
+
+macro_rules! test_ng_analyze {
+    ($name: ident, $code: expr, $($ptr: expr),*) => {
+        #[test]
+        fn $name() {
+            let errors = analyze_errors($code);
+            let pointers: Vec<_> = errors.iter().map(|e| e.code_pointer).collect();
+            assert_eq!(pointers, vec![$(Some($ptr)),*]);
+        }
+    };
+}
+
+#[test]
+fn test_ok_analyze() {
+    assert!(analyze_errors("func: f(a) { let: x; while: a { break; }; return: x; }").is_empty());
+}
+
+#[test]
+fn test_ok_call_function_declared_later() {
+    assert!(analyze_errors(
+        "func: f() { return: g(1, 2); } func: g(a, b) { __trace(a); return: b; }"
+    )
+    .is_empty());
+}
+
+test_ng_analyze!(
+    test_ng_duplicated_variable,
+    "func: f() { let: x; let: x; }",
+    25
+);
+test_ng_analyze!(test_ng_duplicated_argument, "func: f(a, a) {}", 11);
+test_ng_analyze!(
+    test_ng_variable_shadows_argument,
+    "func: f(a) { let: a; }",
+    18
+);
+test_ng_analyze!(test_ng_duplicated_function, "func: f() {} func: f() {}", 19);
+test_ng_analyze!(test_ng_return_in_root, "return: 1;", 0);
+test_ng_analyze!(
+    test_ng_return_in_initializer,
+    "let: g = if: 0 { return: 5; }, h = while: 1 { if: 0 { return: 6; }; break; };",
+    17,
+    54
+);
+test_ng_analyze!(test_ng_expression_in_root, "1 + 2; 3;", 0, 7);
+test_ng_analyze!(
+    test_ng_break_outside_loop,
+    "func: f() { break; continue; }",
+    12,
+    19
+);
+test_ng_analyze!(test_ng_nested_function, "func: f() { func: g() {} }", 18);
+test_ng_analyze!(
+    test_ng_unknown_variable,
+    "func: f(a) { return: a + b; }",
+    25
+);
+test_ng_analyze!(
+    test_ng_variable_used_before_declaration,
+    "func: f() { x = 1; let: x; }",
+    12
+);
+test_ng_analyze!(
+    test_ng_variable_of_other_function,
+    "func: f() { let: x; } func: g() { return: x; }",
+    42
+);
+test_ng_analyze!(test_ng_unknown_function, "func: f() { g(); }", 12);
+test_ng_analyze!(
+    test_ng_wrong_number_of_arguments,
+    "func: f(a) { f(1, 2); __trace(); }",
+    13,
+    22
+);
+
+#[test]
+fn test_ok_block_scoped_variable() {
+    assert!(analyze_errors(
+        "func: f(a) { let: x; while: a { let: x; let: y; if: y { let: x; }; }; if: a { let: y; }; }"
+    )
+    .is_empty());
+}
+
+test_ng_analyze!(
+    test_ng_duplicated_block_variable,
+    "func: f(a) { while: a { let: x; let: x; }; }",
+    37
+);
+test_ng_analyze!(
+    test_ng_block_variable_out_of_scope,
+    "func: f(a) { while: a { let: x; }; return: x; }",
+    43
+);
+
+#[test]
+fn test_ok_global_variable() {
+    assert!(analyze_errors(
+        "func: f() { g = g + 1; let: g; g = 2; } let: g; func: h(g) { return: g; }"
+    )
+    .is_empty());
+}
+
+test_ng_analyze!(
+    test_ng_global_variable_conflicts_with_function,
+    "func: f() {} let: f;",
+    18
+);
+
+#[test]
+fn test_ok_variable_initializer() {
+    assert!(analyze_errors(
+        "let: g = 3, h = g * f(2); func: f(a) { let: x = a * g, y = x + 1; if: y { let: x = x; }; return: x; }"
+    )
+    .is_empty());
+}
+
+test_ng_analyze!(
+    test_ng_variable_initializer_refers_itself,
+    "func: f() { let: x = x; }",
+    21
+);
+
+#[test]
+fn test_ok_array() {
+    assert!(analyze_errors(
+        "let: memo[10]; func: f(i) { let: a[3], n = 2; a[i] = memo[a[0]] + n; while: i { let: b[2]; b[1] = a[2]; }; return: a[i]; }"
+    )
+    .is_empty());
+}
+
+test_ng_analyze!(
+    test_ng_array_without_index,
+    "func: f() { let: a[2], x; x = a; a = 1; }",
+    30,
+    33
+);
+test_ng_analyze!(
+    test_ng_index_not_array,
+    "func: f() { let: x; return: x[0]; }",
+    28
+);
+test_ng_analyze!(test_ng_unknown_array, "func: f() { return: a[b]; }", 20, 22);
+
+test_ng_analyze!(
+    test_ng_string_outside_puts,
+    "func: f() { let: x = \"a\"; __put_int(\"b\"); }",
+    21,
+    36
+);
+test_ng_analyze!(
+    test_ng_puts_without_string,
+    "func: f() { __puts(1); __puts(); }",
+    12,
+    23
+);
+
+#[test]
+fn test_ng_duplicated_name_label() {
+    let errors = analyze_errors("func: f() { let: xy; let: xy = 1; }");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, Some(code::DUPLICATED_NAME));
+    assert_eq!(errors[0].span, Some(Span::new(26, 28)));
+    assert_eq!(
+        errors[0].labels,
+        vec![(Span::new(17, 19), "`xy` first declared here".to_owned())]
+    );
+
+    let errors = analyze_errors("func: f() {} func: f() {}");
+    assert_eq!(errors[0].labels[0].0, Span::new(6, 7));
+}
+
+#[test]
+fn test_ng_error_code() {
+    let codes = |code: &str| -> Vec<_> { analyze_errors(code).iter().map(|e| e.code).collect() };
+    assert_eq!(
+        codes("func: f() { g(); x = 1; break; }"),
+        vec![
+            Some(code::UNKNOWN_FUNCTION),
+            Some(code::UNKNOWN_VARIABLE),
+            Some(code::OUTSIDE_OF_LOOP)
+        ]
+    );
+    assert_eq!(
+        codes("func: f(a) { f(); return: a[0]; }"),
+        vec![Some(code::ARGUMENT_COUNT), Some(code::ARRAY_MISMATCH)]
+    );
+}
+
+#[test]
+fn test_ng_invalid_assignment() {
+    let errors = analyze_errors("func: f() { 1 = 2; (a + 1) += 3; return: 0; }");
+    let codes: Vec<_> = errors.iter().map(|e| (e.code, e.span)).collect();
+    assert_eq!(
+        codes,
+        vec![
+            (Some(code::INVALID_ASSIGNMENT), Some(Span::new(12, 13))),
+            (Some(code::INVALID_ASSIGNMENT), Some(Span::new(20, 25))),
+            (Some(code::UNKNOWN_VARIABLE), Some(Span::new(20, 21))),
+        ]
+    );
+}
+
+#[test]
+fn test_ng_main() {
+    let errors = analyze_program_errors("func: f() { return: 0; }");
+    let codes: Vec<_> = errors.iter().map(|e| (e.code, e.span)).collect();
+    assert_eq!(codes, vec![(Some(code::INVALID_MAIN), None)]);
+
+    let errors = analyze_program_errors("let: x; func: main(a, b) { return: a; }");
+    let codes: Vec<_> = errors.iter().map(|e| (e.code, e.span)).collect();
+    assert_eq!(
+        codes,
+        vec![(Some(code::INVALID_MAIN), Some(Span::new(14, 18)))]
+    );
+
+    assert!(analyze_program_errors("func: main() { return: 0; }").is_empty());
+}
@@ -1,12 +1,17 @@
 use std::{iter, str};
 
-use crate::{base::CodeParseErrorInternal, code_parse_error};
+use crate::{
+    base::{CodeParseErrorInternal, Span, Value},
+    code_parse_error,
+    diagnostic::code,
+};
 
 #[derive(Debug)]
 pub enum Keyword {
     Let,
     Func,
     If,
+    Elsif,
     Else,
     While,
     Return,
@@ -16,58 +21,257 @@ pub enum Keyword {
 
 #[derive(Debug)]
 pub enum Token {
-    Number(i64),
+    Number(Value),
+    String(String), // "..."
     Identifier(String),
     Keyword(Keyword),
     Plus,
     Minus,
     Asterisk,
     Slash,
+    Percent,
+    Ampersand,
+    VerticalBar,
+    Caret,
     Exclamation,
     SingleEqual,
+    PlusEqual,     // +=
+    MinusEqual,    // -=
+    AsteriskEqual, // *=
+    SlashEqual,    // /=
+    PercentEqual,  // %=
     DoubleEqual,
     NotEqual,
     Less,
     Greater,
     LessEqual,
     GreaterEqual,
-    ParenthesisL, // (
-    ParenthesisR, // )
-    BracketL,     // [
-    BracketR,     // ]
-    BraceL,       // {
-    BraceR,       // }
-    Semicolon,    // ;
-    Colon,        // ;
-    Comma,        //,
+    DoubleLess,        // <<
+    DoubleGreater,     // >>
+    DoubleAmpersand,   // &&
+    DoubleVerticalBar, // ||
+    ParenthesisL,      // (
+    ParenthesisR,      // )
+    BracketL,          // [
+    BracketR,          // ]
+    BraceL,            // {
+    BraceR,            // }
+    Semicolon,         // ;
+    Colon,             // ;
+    Comma,             //,
     Invalid,
 }
 
+#[derive(Debug)]
 pub struct TokenInfo {
     pub code_pointer: usize,
+    pub end: usize, // トークンの直後の位置
 }
 
 pub type PrettyToken = (Token, TokenInfo);
 
 impl TokenInfo {
     fn new(code_pointer: usize) -> Self {
-        TokenInfo { code_pointer }
+        TokenInfo {
+            code_pointer,
+            end: code_pointer,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.code_pointer, self.end)
     }
 }
 
-fn parse_number(iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>) -> Token {
+// 123, 1_000, 0x1F, 0b1010
+fn parse_number(
+    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
+) -> Result<Token, CodeParseErrorInternal> {
     // token レベルでは負の数を扱うことはできない
-    let mut value = 0 as i64;
-    while let Some((_, c)) = iter.peek() {
-        if !c.is_ascii_digit() {
-            // TODO: 0x
+    let (begin, _) = *iter.peek().unwrap();
+    let mut radix = 10;
+    if let Some((_, '0')) = iter.peek() {
+        iter.next();
+        match iter.peek() {
+            Some((_, 'x')) | Some((_, 'X')) => radix = 16,
+            Some((_, 'b')) | Some((_, 'B')) => radix = 2,
+            _ => (),
+        }
+        if radix != 10 {
+            iter.next();
+        }
+    }
+    // NOTE: 桁あふれしたら None
+    let mut value = Some(Value::from(0));
+    let mut has_digit = radix == 10; // 10 進数なら先頭の数字を読んでいる
+    let mut error = None;
+    while let Some((idx, c)) = iter.peek() {
+        if *c == '_' {
+            iter.next();
+            continue;
+        }
+        if !c.is_ascii_alphanumeric() {
             break;
         }
-        let d = c.to_digit(10).unwrap();
-        value = value * 10 + d as i64;
+        match c.to_digit(radix) {
+            Some(d) => {
+                value = value
+                    .and_then(|v| v.checked_mul(&Value::from(radix as i64)))
+                    .and_then(|v| v.checked_add(&Value::from(d as i64)));
+                has_digit = true;
+            }
+            None => {
+                error.get_or_insert_with(|| {
+                    code_parse_error!(*idx, format!("invalid digit in number literal: {}", c))
+                });
+            }
+        }
         iter.next();
     }
-    Token::Number(value)
+    if let Some(e) = error {
+        return Err(e);
+    }
+    if !has_digit {
+        return Err(code_parse_error!(
+            begin,
+            "missing digits in number literal".to_owned()
+        ));
+    }
+    match value {
+        Some(v) => Ok(Token::Number(v)),
+        None => Err(code_parse_error!(
+            begin,
+            "number literal is too large".to_owned()
+        )),
+    }
+}
+
+// `\` に続く文字を読む
+fn parse_escape(
+    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
+) -> Result<char, CodeParseErrorInternal> {
+    let (idx, c) = match iter.next() {
+        Some(x) => x,
+        None => return Err(code_parse_error!("unexpected end of input".to_owned())),
+    };
+    match c {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '\\' | '\'' | '"' => Ok(c),
+        _ => Err(code_parse_error!(idx, format!("unknown escape: \\{}", c))),
+    }
+}
+
+// エラーから復帰するため、同じ行の閉じる引用符まで読み飛ばす
+fn skip_to_quote(
+    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
+    quote: char,
+) {
+    for (_, c) in iter.by_ref() {
+        if c == quote || c == '\n' {
+            break;
+        }
+    }
+}
+
+// 'a' is a number (the code point)
+fn parse_char(
+    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
+) -> Result<Token, CodeParseErrorInternal> {
+    let (begin, _) = iter.next().unwrap(); // '
+    let c = match iter.next() {
+        Some((_, '\\')) => match parse_escape(iter) {
+            Ok(c) => c,
+            Err(e) => {
+                skip_to_quote(iter, '\'');
+                return Err(e);
+            }
+        },
+        Some((idx, '\'')) => return Err(code_parse_error!(idx, "empty char literal".to_owned())),
+        Some((_, '\n')) | None => {
+            return Err(code_parse_error!(
+                begin,
+                "unterminated char literal".to_owned()
+            ))
+        }
+        Some((_, c)) => c,
+    };
+    match iter.next() {
+        Some((_, '\'')) => Ok(Token::Number(Value::from(c as i64))),
+        Some((_, '\n')) | None => Err(code_parse_error!(
+            begin,
+            "unterminated char literal".to_owned()
+        )),
+        _ => {
+            skip_to_quote(iter, '\'');
+            Err(code_parse_error!(
+                begin,
+                "char literal must be one character".to_owned()
+            ))
+        }
+    }
+}
+
+fn parse_string(
+    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
+) -> Result<Token, CodeParseErrorInternal> {
+    let (begin, _) = iter.next().unwrap(); // "
+    let mut text = String::new();
+    let mut error = None; // 最初のエラー。閉じる " まで読んでから返す
+    loop {
+        match iter.next() {
+            Some((_, '"')) => {
+                return match error {
+                    Some(e) => Err(e),
+                    None => Ok(Token::String(text)),
+                }
+            }
+            Some((_, '\\')) => match parse_escape(iter) {
+                Ok(c) => text.push(c),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            },
+            Some((_, '\n')) | None => {
+                return Err(code_parse_error!(
+                    begin,
+                    "unterminated string literal".to_owned()
+                ))
+            }
+            Some((_, c)) => text.push(c),
+        }
+    }
+}
+
+// /* comment */ の `/*` の後を読み飛ばす。入れ子にできる
+fn skip_block_comment(
+    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
+    begin: usize,
+) -> Result<(), CodeParseErrorInternal> {
+    let mut depth = 1;
+    let mut prev = None;
+    for (_, c) in iter.by_ref() {
+        match (prev, c) {
+            (Some('/'), '*') => {
+                depth += 1;
+                prev = None;
+                continue;
+            }
+            (Some('*'), '/') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+                prev = None;
+                continue;
+            }
+            _ => (),
+        }
+        prev = Some(c);
+    }
+    Err(code_parse_error!(begin, "unterminated comment".to_owned()))
 }
 
 fn determine_keyword_or_identifier(id: String) -> Token {
@@ -75,6 +279,7 @@ fn determine_keyword_or_identifier(id: String) -> Token {
         "let" => Token::Keyword(Keyword::Let),
         "func" => Token::Keyword(Keyword::Func),
         "if" => Token::Keyword(Keyword::If),
+        "elsif" => Token::Keyword(Keyword::Elsif),
         "else" => Token::Keyword(Keyword::Else),
         "while" => Token::Keyword(Keyword::While),
         "return" => Token::Keyword(Keyword::Return),
@@ -105,24 +310,32 @@ fn parse_identifier(
     }
 }
 
-fn parse_to_tokens_internal(
-    iter: &mut iter::Peekable<iter::Enumerate<impl Iterator<Item = char>>>,
-) -> (Vec<PrettyToken>, Vec<CodeParseErrorInternal>) {
+fn parse_to_tokens_internal(text: &str) -> (Vec<PrettyToken>, Vec<CodeParseErrorInternal>) {
+    let iter = &mut text.chars().enumerate().peekable();
     let mut tokens = Vec::<PrettyToken>::new();
     let mut parse_errors = Vec::<CodeParseErrorInternal>::new();
+    // NOTE: 1 回のループで読むトークンは高々 1 つなので、次のループの先頭がトークンの終端になる
+    let mut closed = 0;
     while let Some((idx, c)) = iter.peek() {
+        for (_, info) in tokens[closed..].iter_mut() {
+            info.end = *idx;
+        }
+        closed = tokens.len();
         if *c == '#' {
+            // # comment #
+            let begin = *idx;
             iter.next();
-            while let Some((_, c2)) = iter.next() {
-                if c2 == '#' {
-                    break;
-                }
+            if !iter.by_ref().any(|(_, c2)| c2 == '#') {
+                parse_errors.push(code_parse_error!(begin, "unterminated comment".to_owned()));
             }
             continue;
         }
         let info = TokenInfo::new(*idx);
         if c.is_ascii_digit() {
-            tokens.push((parse_number(iter), info));
+            match parse_number(iter) {
+                Ok(t) => tokens.push((t, info)),
+                Err(e) => parse_errors.push(e),
+            }
         } else if c.is_whitespace() {
             iter.next();
             // c.is_ascii()
@@ -132,6 +345,18 @@ fn parse_to_tokens_internal(
                     tokens.push((parse_identifier(iter), info));
                     continue;
                 }
+                '\'' | '"' => {
+                    let res = if *c == '"' {
+                        parse_string(iter)
+                    } else {
+                        parse_char(iter)
+                    };
+                    match res {
+                        Ok(t) => tokens.push((t, info)),
+                        Err(e) => parse_errors.push(e),
+                    }
+                    continue;
+                }
                 '=' => {
                     iter.next();
                     match iter.peek() {
@@ -146,6 +371,7 @@ fn parse_to_tokens_internal(
                     iter.next();
                     match iter.peek() {
                         Some((_, c)) if *c == '=' => Token::LessEqual,
+                        Some((_, c)) if *c == '<' => Token::DoubleLess,
                         _ => {
                             tokens.push((Token::Less, info));
                             continue;
@@ -156,6 +382,7 @@ fn parse_to_tokens_internal(
                     iter.next();
                     match iter.peek() {
                         Some((_, c)) if *c == '=' => Token::GreaterEqual,
+                        Some((_, c)) if *c == '>' => Token::DoubleGreater,
                         _ => {
                             tokens.push((Token::Greater, info));
                             continue;
@@ -172,10 +399,92 @@ fn parse_to_tokens_internal(
                         }
                     }
                 }
-                '+' => Token::Plus,
-                '-' => Token::Minus,
-                '*' => Token::Asterisk,
-                '/' => Token::Slash,
+                '+' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, c)) if *c == '=' => Token::PlusEqual,
+                        _ => {
+                            tokens.push((Token::Plus, info));
+                            continue;
+                        }
+                    }
+                }
+                '-' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, c)) if *c == '=' => Token::MinusEqual,
+                        _ => {
+                            tokens.push((Token::Minus, info));
+                            continue;
+                        }
+                    }
+                }
+                '*' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, c)) if *c == '=' => Token::AsteriskEqual,
+                        _ => {
+                            tokens.push((Token::Asterisk, info));
+                            continue;
+                        }
+                    }
+                }
+                '%' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, c)) if *c == '=' => Token::PercentEqual,
+                        _ => {
+                            tokens.push((Token::Percent, info));
+                            continue;
+                        }
+                    }
+                }
+                '&' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, c)) if *c == '&' => Token::DoubleAmpersand,
+                        _ => {
+                            tokens.push((Token::Ampersand, info));
+                            continue;
+                        }
+                    }
+                }
+                '|' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, c)) if *c == '|' => Token::DoubleVerticalBar,
+                        _ => {
+                            tokens.push((Token::VerticalBar, info));
+                            continue;
+                        }
+                    }
+                }
+                '^' => Token::Caret,
+                '/' => {
+                    iter.next();
+                    match iter.peek() {
+                        Some((_, '/')) => {
+                            // line comment
+                            for (_, c2) in iter.by_ref() {
+                                if c2 == '\n' {
+                                    break;
+                                }
+                            }
+                        }
+                        Some((_, '*')) => {
+                            iter.next();
+                            if let Err(e) = skip_block_comment(iter, info.code_pointer) {
+                                parse_errors.push(e);
+                            }
+                        }
+                        Some((_, '=')) => {
+                            iter.next();
+                            tokens.push((Token::SlashEqual, info));
+                        }
+                        _ => tokens.push((Token::Slash, info)),
+                    }
+                    continue;
+                }
                 '(' => Token::ParenthesisL,
                 ')' => Token::ParenthesisR,
                 '[' => Token::BracketL,
@@ -195,11 +504,19 @@ fn parse_to_tokens_internal(
             iter.next();
         }
     }
+    let len = text.chars().count();
+    for (_, info) in tokens[closed..].iter_mut() {
+        info.end = len;
+    }
+    let parse_errors = parse_errors
+        .into_iter()
+        .map(|e| e.with_code(code::INVALID_TOKEN))
+        .collect();
     (tokens, parse_errors)
 }
 
 pub fn parse_to_tokens(text: &str) -> Result<Vec<PrettyToken>, Vec<CodeParseErrorInternal>> {
-    let (tk, err) = parse_to_tokens_internal(&mut text.chars().enumerate().peekable());
+    let (tk, err) = parse_to_tokens_internal(text);
     if err.is_empty() {
         Ok(tk)
     } else {
@@ -1,19 +1,14 @@
-use std::{
-    iter::{self, Enumerate, Peekable},
-    str::Chars,
-};
-
 use crate::{
-    base::CodeParseErrorInternal,
+    base::{CodeParseErrorInternal, Value},
     token_parser::{parse_to_tokens_internal, Keyword, Token},
 };
 
 use super::PrettyToken;
 
 fn res_parse_to_tokens_internal(
-    iter: &mut iter::Peekable<iter::Enumerate<Chars>>,
+    code: &str,
 ) -> Result<Vec<PrettyToken>, Vec<CodeParseErrorInternal>> {
-    let (tk, err) = parse_to_tokens_internal(iter);
+    let (tk, err) = parse_to_tokens_internal(code);
 
     if err.is_empty() {
         Ok(tk)
@@ -22,16 +17,12 @@ fn res_parse_to_tokens_internal(
     }
 }
 
-fn to_iter(code: &str) -> Peekable<Enumerate<Chars>> {
-    code.chars().enumerate().peekable()
-}
-
 macro_rules! test_ok_parse_single {
     ($name: ident, $val: expr, $($pat:pat)|+ if $cond:expr ) => {
         // note: concat_idents! is only for nightly
         #[test]
         fn $name() -> Result<(), &'static str> {
-            let res = res_parse_to_tokens_internal(&mut to_iter($val)).unwrap();
+            let res = res_parse_to_tokens_internal($val).unwrap();
             let mut it = res.iter();
             if let Some((token, _)) = it.next() {
                 assert_matches!(token, $($pat)|+ if $cond);
@@ -45,7 +36,7 @@ macro_rules! test_ok_parse_single {
         // note: concat_idents! is only for nightly
         #[test]
         fn $name() -> Result<(), &'static str> {
-            let res = res_parse_to_tokens_internal(&mut to_iter($val)).unwrap();
+            let res = res_parse_to_tokens_internal($val).unwrap();
             let mut it = res.iter();
             if let Some((token, _)) = it.next() {
                 assert_matches!(token, $($pat)|+);
@@ -62,7 +53,7 @@ macro_rules! test_ok_parse {
         // note: concat_idents! is only for nightly
         #[test]
         fn $name() {
-            let res = res_parse_to_tokens_internal(&mut to_iter($val)).unwrap();
+            let res = res_parse_to_tokens_internal($val).unwrap();
             {
                 let mut $it = res.iter().map(|pt| &pt.0);
                 $block
@@ -73,7 +64,7 @@ macro_rules! test_ok_parse {
 
 macro_rules! test_ok_parse_number {
     ($name: ident, $val: expr) => {
-        test_ok_parse_single!($name, stringify!($val), Token::Number(n) if *n == $val);
+        test_ok_parse_single!($name, stringify!($val), Token::Number(n) if *n == Value::from($val));
     };
 }
 
@@ -84,15 +75,22 @@ macro_rules! test_ok_parse_identifier {
 }
 
 test_ok_parse_number!(test_ok_pn_1, 50);
+test_ok_parse_number!(test_ok_pn_2, 0);
+test_ok_parse_number!(test_ok_pn_3, 0x1F);
+test_ok_parse_number!(test_ok_pn_4, 0b1010);
+test_ok_parse_number!(test_ok_pn_5, 1_000_000);
+test_ok_parse_number!(test_ok_pn_6, 0xDEAD_BEEF);
+test_ok_parse_number!(test_ok_pn_7, 9223372036854775807);
+test_ok_parse_single!(test_ok_pn_8, "0X1f", Token::Number(n) if *n == Value::from(31));
 test_ok_parse_identifier!(test_ok_pi_1, "sushi123");
 test_ok_parse_identifier!(test_ok_pi_2, "MOCHI_");
 test_ok_parse_identifier!(test_ok_pi_3, "__uni__");
 test_ok_parse_identifier!(test_ok_pi_4, "_998244353");
 
 test_ok_parse!(test_ok_p_1, "2+3", it => {
-    assert_matches!(it.next(), Some(Token::Number(n)) if *n == 2);
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(2));
     assert_matches!(it.next(), Some(Token::Plus));
-    assert_matches!(it.next(), Some(Token::Number(n)) if *n == 3);
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(3));
     assert_matches!(it.next(), None);
 });
 
@@ -104,5 +102,161 @@ test_ok_parse!(test_ok_p_2, "let:a;", it => {
     assert_matches!(it.next(), None);
 });
 
-// TODO: add fail case
-// TODO: add tokeninfo
+test_ok_parse_single!(test_ok_pc_1, "'a'", Token::Number(n) if *n == Value::from(97));
+test_ok_parse_single!(test_ok_pc_2, "'\\n'", Token::Number(n) if *n == Value::from(10));
+test_ok_parse_single!(test_ok_pc_3, "'\\''", Token::Number(n) if *n == Value::from(39));
+test_ok_parse_single!(test_ok_pc_4, "'あ'", Token::Number(n) if *n == Value::from(0x3042));
+test_ok_parse_single!(test_ok_ps_1, "\"hello\"", Token::String(s) if s == "hello");
+test_ok_parse_single!(test_ok_ps_2, "\"a\\tb\\\\\\\"\\n\"", Token::String(s) if s == "a\tb\\\"\n");
+test_ok_parse_single!(test_ok_ps_3, "\"\"", Token::String(s) if s.is_empty());
+
+test_ok_parse!(test_ok_p_3, "__puts(\"a;b\");", it => {
+    assert_matches!(it.next(), Some(Token::Identifier(x)) if *x == "__puts");
+    assert_matches!(it.next(), Some(Token::ParenthesisL));
+    assert_matches!(it.next(), Some(Token::String(x)) if *x == "a;b");
+    assert_matches!(it.next(), Some(Token::ParenthesisR));
+    assert_matches!(it.next(), Some(Token::Semicolon));
+    assert_matches!(it.next(), None);
+});
+
+macro_rules! test_ng_parse {
+    ($name: ident, $val: expr, $($ptr: expr),*) => {
+        #[test]
+        fn $name() {
+            let errors = res_parse_to_tokens_internal($val).unwrap_err();
+            let pointers: Vec<_> = errors.iter().map(|e| e.code_pointer).collect();
+            assert_eq!(pointers, vec![$($ptr),*]);
+        }
+    };
+}
+
+test_ng_parse!(test_ng_pc_empty, "x = '';", Some(5));
+test_ng_parse!(test_ng_pc_too_long, "x = 'ab'; y = 'c';", Some(4));
+test_ng_parse!(test_ng_pc_unterminated, "x = 'a", Some(4));
+test_ng_parse!(test_ng_ps_unterminated, "x = \"abc\ny = 1;", Some(4));
+test_ng_parse!(test_ng_ps_escape, "x = \"a\\qb\";", Some(7));
+
+#[cfg(not(feature = "bigint"))]
+test_ng_parse!(test_ng_pn_overflow, "x = 9223372036854775808;", Some(4));
+#[cfg(not(feature = "bigint"))]
+test_ng_parse!(
+    test_ng_pn_hex_overflow,
+    "x = 0x1_0000_0000_0000_0000;",
+    Some(4)
+);
+#[cfg(feature = "bigint")]
+test_ok_parse_single!(
+    test_ok_pn_bigint,
+    "0x1_0000_0000_0000_0000",
+    Token::Number(n) if n.to_string() == "18446744073709551616"
+);
+test_ng_parse!(test_ng_pn_no_digits, "x = 0x;", Some(4));
+test_ng_parse!(
+    test_ng_pn_invalid_digit,
+    "x = 0b1021 + 12a;",
+    Some(8),
+    Some(15)
+);
+
+test_ok_parse!(test_ok_bit_operators, "a%b&c|d^e<<f>>g<h", it => {
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::Percent));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::Ampersand));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::VerticalBar));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::Caret));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::DoubleLess));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::DoubleGreater));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::Less));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), None);
+});
+
+test_ok_parse!(test_ok_logical_operators, "!a&&b||c&d|e!=f", it => {
+    assert_matches!(it.next(), Some(Token::Exclamation));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::DoubleAmpersand));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::DoubleVerticalBar));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::Ampersand));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::VerticalBar));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::NotEqual));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), None);
+});
+
+test_ok_parse!(test_ok_compound_assign, "a+=b-=c*=d/=e%=f+g", it => {
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::PlusEqual));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::MinusEqual));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::AsteriskEqual));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::SlashEqual));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::PercentEqual));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), Some(Token::Plus));
+    assert_matches!(it.next(), Some(Token::Identifier(_)));
+    assert_matches!(it.next(), None);
+});
+
+test_ok_parse!(test_ok_comment_1, "1 # a # / 2 // 3 + 4\n- 5 // 6", it => {
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(1));
+    assert_matches!(it.next(), Some(Token::Slash));
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(2));
+    assert_matches!(it.next(), Some(Token::Minus));
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(5));
+    assert_matches!(it.next(), None);
+});
+
+test_ok_parse!(test_ok_comment_2, "1 /* a /* b */ c */ 2 /**/ /*/ */ 3", it => {
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(1));
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(2));
+    assert_matches!(it.next(), Some(Token::Number(n)) if *n == Value::from(3));
+    assert_matches!(it.next(), None);
+});
+
+test_ng_parse!(
+    test_ng_comment_unterminated,
+    "x = 1; # comment\ny = 2;",
+    Some(7)
+);
+test_ng_parse!(
+    test_ng_block_comment_unterminated,
+    "x /* a /* b */ c",
+    Some(2)
+);
+
+#[test]
+fn test_ok_token_span() {
+    let res = res_parse_to_tokens_internal("let: xy = 0x1F; # c # \"a\"/=3\n<=").unwrap();
+    let spans: Vec<_> = res
+        .iter()
+        .map(|(_, info)| (info.code_pointer, info.end))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            (0, 3),
+            (3, 4),
+            (5, 7),
+            (8, 9),
+            (10, 14),
+            (14, 15),
+            (22, 25),
+            (25, 27),
+            (27, 28),
+            (29, 31)
+        ]
+    );
+}
@@ -1,15 +1,16 @@
-use std::iter;
+use std::fmt;
 
 use crate::code_parse_error;
+use crate::diagnostic::code;
 
 use crate::token_parser::{Keyword, TokenInfo};
 use crate::tree_parser::statement::parse_to_statements;
 use crate::{
-    base::CodeParseErrorInternal,
-    token_parser::{PrettyToken, Token},
+    base::{CodeParseErrorInternal, Span, Value},
+    token_parser::Token,
 };
 
-use super::Statement;
+use super::{Statement, TokenIter};
 
 //
 
@@ -39,13 +40,26 @@ macro_rules! match_expect_token_unused {
 
 //
 
-#[derive(Clone)] // TODO: REMOVE
+#[derive(Clone, Debug)] // TODO: REMOVE Clone
 pub enum Operator2 {
     Plus,
     Minus,
     Multiply,
     Divide,
+    Modulo,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    LogicalAnd,
+    LogicalOr,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    MultiplyAssign,
+    DivideAssign,
+    ModuloAssign,
     Equal,
     NotEqual,
     Less,
@@ -54,37 +68,117 @@ pub enum Operator2 {
     GreaterEqual,
 }
 
-#[derive(Clone)] // TODO: REMOVE
+impl Operator2 {
+    pub fn is_assign(&self) -> bool {
+        matches!(self, Operator2::Assign) || self.compound_operator().is_some()
+    }
+
+    // 複合代入 `a += b` なら、代入前に行う演算 `+` を返す
+    pub fn compound_operator(&self) -> Option<Operator2> {
+        match self {
+            Operator2::PlusAssign => Some(Operator2::Plus),
+            Operator2::MinusAssign => Some(Operator2::Minus),
+            Operator2::MultiplyAssign => Some(Operator2::Multiply),
+            Operator2::DivideAssign => Some(Operator2::Divide),
+            Operator2::ModuloAssign => Some(Operator2::Modulo),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Operator2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Operator2::Plus => "+",
+            Operator2::Minus => "-",
+            Operator2::Multiply => "*",
+            Operator2::Divide => "/",
+            Operator2::Modulo => "%",
+            Operator2::BitAnd => "&",
+            Operator2::BitOr => "|",
+            Operator2::BitXor => "^",
+            Operator2::ShiftLeft => "<<",
+            Operator2::ShiftRight => ">>",
+            Operator2::LogicalAnd => "&&",
+            Operator2::LogicalOr => "||",
+            Operator2::Assign => "=",
+            Operator2::PlusAssign => "+=",
+            Operator2::MinusAssign => "-=",
+            Operator2::MultiplyAssign => "*=",
+            Operator2::DivideAssign => "/=",
+            Operator2::ModuloAssign => "%=",
+            Operator2::Equal => "==",
+            Operator2::NotEqual => "!=",
+            Operator2::Less => "<",
+            Operator2::LessEqual => "<=",
+            Operator2::Greater => ">",
+            Operator2::GreaterEqual => ">=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug)] // TODO: REMOVE Clone
 pub enum Operator1 {
     Negative,
+    Not,
 }
 
-#[derive(Clone)] // TODO: REMOVE
+impl fmt::Display for Operator1 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Operator1::Negative => "-",
+            Operator1::Not => "!",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// NOTE: 末尾の Span は式全体の範囲
+#[derive(Clone, Debug)] // TODO: REMOVE Clone
 pub enum Expression {
-    Operation1(Operator1, Box<Expression>),
-    Operation2(Operator2, Box<Expression>, Box<Expression>),
-    If(Box<Expression>, Vec<Statement>, Vec<Statement>),
-    While(Box<Expression>, Vec<Statement>),
-    Function(String, Vec<Box<Expression>>),
-    Factor(i64),
-    Variable(String),
+    Operation1(Operator1, Box<Expression>, usize, Span), // usize: code_pointer of the operator
+    Operation2(Operator2, Box<Expression>, Box<Expression>, usize, Span), // usize: code_pointer of the operator
+    If(Vec<(Box<Expression>, Vec<Statement>)>, Vec<Statement>, Span), // (cond, block) の列と else
+    While(Box<Expression>, Vec<Statement>, Span),
+    Function(String, Vec<Expression>, Span),
+    Factor(Value, Span),
+    String(String, Span),
+    Variable(String, Span),
+    ArrayElement(String, Box<Expression>, Span), // a[i]
     Invalid(usize), // NOTE: CodeParseError に関連する情報を入れる。今は CodeParseError の
                     // インデックスを利用。 本来は ExpressionBuilder 単位ではなく、全体で独立した
                     // インデックスを利用するべき。
                     // 構文木のノードからエラー情報を参照したい目的は特に無いので、使われていない。
 }
 
+impl Expression {
+    // 式全体の範囲。Invalid は範囲を持たない
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expression::Operation1(_, _, _, span)
+            | Expression::Operation2(_, _, _, _, span)
+            | Expression::If(_, _, span)
+            | Expression::While(_, _, span)
+            | Expression::Function(_, _, span)
+            | Expression::Factor(_, span)
+            | Expression::String(_, span)
+            | Expression::Variable(_, span)
+            | Expression::ArrayElement(_, _, span) => Some(*span),
+            Expression::Invalid(_) => None,
+        }
+    }
+}
+
 //
 
 struct ExpressionBuilder<'b: 'a, 'a> {
-    iter: &'a mut iter::Peekable<std::slice::Iter<'b, PrettyToken>>,
+    iter: &'a mut TokenIter<'b>,
     code_parse_error: Vec<CodeParseErrorInternal>,
 }
 
 impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
-    fn parse(
-        iter: &'a mut iter::Peekable<std::slice::Iter<'b, PrettyToken>>,
-    ) -> (Box<Expression>, Vec<CodeParseErrorInternal>) {
+    fn parse(iter: &'a mut TokenIter<'b>) -> (Box<Expression>, Vec<CodeParseErrorInternal>) {
         let mut b = Self {
             iter,
             code_parse_error: vec![],
@@ -95,23 +189,70 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
 
     fn add_parse_error(&mut self, token_info: &TokenInfo, msg: String) -> usize {
         let i = self.code_parse_error.len();
-        self.code_parse_error
-            .push(code_parse_error!(token_info.code_pointer, msg.to_string()));
+        self.code_parse_error.push(
+            code_parse_error!(token_info.code_pointer, msg)
+                .with_code(code::UNEXPECTED_TOKEN)
+                .with_span(token_info.span()),
+        );
         i
     }
     fn add_end_error(&mut self, msg: String) -> usize {
         let i = self.code_parse_error.len();
-        self.code_parse_error
-            .push(code_parse_error!(msg.to_string()));
+        let end = self.iter.end;
+        self.code_parse_error.push(
+            code_parse_error!(msg)
+                .with_code(code::UNEXPECTED_END)
+                .with_span(Span::new(end, end)),
+        );
         i
     }
 
-    fn parse_to_expression_tree_function(&mut self, name: &String) -> Box<Expression> {
+    // 閉じ括弧を読む。無ければ読まずにエラーとする (文の立て直しに任せる)
+    // error_count: 括弧を開いた時のエラーの数。括弧の中で既に報告していれば重ねて報告しない
+    fn parse_to_expression_tree_close(
+        &mut self,
+        is_close: fn(&Token) -> bool,
+        name: &str,
+        error_count: usize,
+    ) -> Result<(), usize> {
+        match self.iter.peek() {
+            Some((token, _)) if is_close(token) => {
+                self.iter.next();
+                Ok(())
+            }
+            _ if self.code_parse_error.len() > error_count => Err(error_count),
+            Some((_, token_info)) => {
+                Err(self
+                    .add_parse_error(token_info, format!("unexpected token: expected {}", name)))
+            }
+            None => Err(self.add_end_error("unexpected end of input".to_owned())),
+        }
+    }
+
+    // while, if のブロックの `{` を読む
+    // 無ければ、同じ文の中に `{` があればそこまで読み飛ばし、無ければあったものとして続ける
+    fn parse_to_expression_tree_block_begin(&mut self) {
+        if match_expect_token!(self, self.iter.peek(), Token::BraceL).is_err() {
+            if !self
+                .iter
+                .lookahead_in_statement(|t| matches!(t, Token::BraceL))
+            {
+                return;
+            }
+            while !matches!(self.iter.peek(), Some((Token::BraceL, _))) {
+                self.iter.next();
+            }
+        }
+        self.iter.next();
+    }
+
+    fn parse_to_expression_tree_function(&mut self, name: &str, begin: usize) -> Box<Expression> {
         if let Err(e) = match_expect_token!(self, self.iter.next(), Token::ParenthesisL) {
             return Box::new(Expression::Invalid(e));
         }
 
-        let mut args = Vec::<Box<Expression>>::new();
+        let error_count = self.code_parse_error.len();
+        let mut args = Vec::<Expression>::new();
         enum State {
             L,
             Eval,
@@ -120,13 +261,28 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
         let mut state = State::L;
         loop {
             match self.iter.peek() {
+                Some((Token::Semicolon, _)) | Some((Token::BraceR, _)) | None => {
+                    // 文の終わりまで `)` が無い
+                    let e = self
+                        .parse_to_expression_tree_close(
+                            |t| matches!(t, Token::ParenthesisR),
+                            "Token::ParenthesisR",
+                            error_count,
+                        )
+                        .unwrap_err();
+                    return Box::new(Expression::Invalid(e));
+                }
                 Some((Token::ParenthesisR, token_info)) => {
                     if let State::Comma = state {
                         // weak syntax error and proceed parsing
                         self.add_parse_error(token_info, "unexpected comma".to_owned());
                     }
                     self.iter.next();
-                    return Box::new(Expression::Function(name.clone(), args));
+                    return Box::new(Expression::Function(
+                        name.to_owned(),
+                        args,
+                        self.iter.span_from(begin),
+                    ));
                 }
                 Some((Token::Comma, token_info)) => {
                     if let State::Eval = state {
@@ -138,100 +294,141 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
                     self.iter.next();
                 }
                 Some((_, token_info)) => {
+                    let arg_begin = self.iter.begin();
+                    let e = self.parse_to_expression_tree_root();
+                    if self.iter.begin() == arg_begin {
+                        // 式として読めるトークンが無い。読み進めないと終わらない
+                        return e;
+                    }
                     if let State::Eval = state {
                         // weak syntax error and proceed parsing
                         self.add_parse_error(token_info, "missing comma".to_owned());
                     }
-                    let e = self.parse_to_expression_tree_root();
-                    args.push(e);
+                    args.push(*e);
                     state = State::Eval;
                 }
-                None => {
-                    return Box::new(Expression::Invalid(
-                        self.add_end_error("unexpected end of input".to_owned()),
-                    ))
-                }
             }
         }
     }
 
     fn parse_to_expression_tree_factor(&mut self) -> Box<Expression> {
         match self.iter.peek() {
-            Some((Token::Number(val), _)) => {
+            Some((Token::Number(val), token_info)) => {
                 self.iter.next();
-                return Box::new(Expression::Factor(*val));
+                Box::new(Expression::Factor(val.clone(), token_info.span()))
             }
-            Some((Token::Identifier(id), _)) => {
+            Some((Token::String(text), token_info)) => {
+                self.iter.next();
+                Box::new(Expression::String(text.clone(), token_info.span()))
+            }
+            Some((Token::Identifier(id), token_info)) => {
                 // TODO: confirm whether the identifier is reserved e.g. func
+                let code_pointer = token_info.code_pointer;
                 self.iter.next();
                 if let Some((Token::ParenthesisL, _)) = self.iter.peek() {
-                    return self.parse_to_expression_tree_function(id);
+                    return self.parse_to_expression_tree_function(id, code_pointer);
                 }
-                return Box::new(Expression::Variable(id.clone()));
+                if let Some((Token::BracketL, _)) = self.iter.peek() {
+                    self.iter.next();
+                    let error_count = self.code_parse_error.len();
+                    let index = self.parse_to_expression_tree_root();
+                    if self
+                        .parse_to_expression_tree_close(
+                            |t| matches!(t, Token::BracketR),
+                            "Token::BracketR",
+                            error_count,
+                        )
+                        .is_err()
+                    {
+                        // weak syntax error and proceed parsing
+                    }
+                    return Box::new(Expression::ArrayElement(
+                        id.clone(),
+                        index,
+                        self.iter.span_from(code_pointer),
+                    ));
+                }
+                Box::new(Expression::Variable(id.clone(), token_info.span()))
             }
             Some((Token::ParenthesisL, _)) => {
                 self.iter.next();
+                let error_count = self.code_parse_error.len();
                 let e = self.parse_to_expression_tree_root();
 
-                if let Err(_) = match_expect_token!(self, self.iter.next(), Token::ParenthesisR) {
+                if self
+                    .parse_to_expression_tree_close(
+                        |t| matches!(t, Token::ParenthesisR),
+                        "Token::ParenthesisR",
+                        error_count,
+                    )
+                    .is_err()
+                {
                     // weak syntax error and proceed parsing
                 }
-                return e;
-            }
-            Some((_, token_info)) => {
-                return Box::new(Expression::Invalid(
-                    self.add_parse_error(token_info, "unexpected token".to_owned()),
-                ));
-            }
-            _ => {
-                return Box::new(Expression::Invalid(
-                    self.add_end_error("unexpected end of input".to_owned()),
-                ));
+                e
             }
+            Some((_, token_info)) => Box::new(Expression::Invalid(
+                self.add_parse_error(token_info, "unexpected token".to_owned()),
+            )),
+            _ => Box::new(Expression::Invalid(
+                self.add_end_error("unexpected end of input".to_owned()),
+            )),
         }
     }
 
     fn parse_to_expression_tree_unary(&mut self) -> Box<Expression> {
         let mut op_stack = vec![];
-        loop {
-            // `----` のような単行演算子が連続するものも許容する
-            // よって `++x` のようなインクリメントは実装不可になる
-            if let Some(token) = self.iter.peek() {
-                match token {
-                    (Token::Minus, _) => op_stack.push(Operator1::Negative),
-                    _ => break,
-                }
-            } else {
-                break;
+        // `----` のような単行演算子が連続するものも許容する
+        // よって `++x` のようなインクリメントは実装不可になる
+        while let Some((token, token_info)) = self.iter.peek() {
+            let op = match token {
+                Token::Minus => Operator1::Negative,
+                Token::Exclamation => Operator1::Not,
+                _ => break,
             };
+            op_stack.push((op, token_info.code_pointer));
             self.iter.next();
         }
         let mut left = self.parse_to_expression_tree_factor();
-        while let Some(op) = op_stack.pop() {
-            left = Box::new(Expression::Operation1(op, left))
+        while let Some((op, code_pointer)) = op_stack.pop() {
+            left = Box::new(Expression::Operation1(
+                op,
+                left,
+                code_pointer,
+                self.iter.span_from(code_pointer),
+            ))
         }
         left
     }
 
     fn parse_to_expression_tree_mul(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
         let mut left = self.parse_to_expression_tree_unary();
         loop {
             let op = if let Some(token) = self.iter.peek() {
                 match token {
                     (Token::Asterisk, _) => Operator2::Multiply,
                     (Token::Slash, _) => Operator2::Divide,
+                    (Token::Percent, _) => Operator2::Modulo,
                     _ => return left,
                 }
             } else {
                 return left;
             };
-            self.iter.next();
+            let code_pointer = self.iter.next().unwrap().1.code_pointer;
             let right = self.parse_to_expression_tree_unary();
-            left = Box::new(Expression::Operation2(op, left, right))
+            left = Box::new(Expression::Operation2(
+                op,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
         }
     }
 
     fn parse_to_expression_tree_plus(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
         let mut left = self.parse_to_expression_tree_mul();
         loop {
             let op = if let Some(token) = self.iter.peek() {
@@ -243,14 +440,46 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
             } else {
                 return left;
             };
-            self.iter.next();
+            let code_pointer = self.iter.next().unwrap().1.code_pointer;
             let right = self.parse_to_expression_tree_mul();
-            left = Box::new(Expression::Operation2(op, left, right));
+            left = Box::new(Expression::Operation2(
+                op,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
         }
     }
 
-    fn parse_to_expression_tree_compare(&mut self) -> Box<Expression> {
+    fn parse_to_expression_tree_shift(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
         let mut left = self.parse_to_expression_tree_plus();
+        loop {
+            let op = if let Some(token) = self.iter.peek() {
+                match token {
+                    (Token::DoubleLess, _) => Operator2::ShiftLeft,
+                    (Token::DoubleGreater, _) => Operator2::ShiftRight,
+                    _ => return left,
+                }
+            } else {
+                return left;
+            };
+            let code_pointer = self.iter.next().unwrap().1.code_pointer;
+            let right = self.parse_to_expression_tree_plus();
+            left = Box::new(Expression::Operation2(
+                op,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
+        }
+    }
+
+    fn parse_to_expression_tree_compare(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
+        let mut left = self.parse_to_expression_tree_shift();
         loop {
             let op = if let Some(token) = self.iter.peek() {
                 match token {
@@ -265,25 +494,130 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
             } else {
                 return left;
             };
+            let code_pointer = self.iter.next().unwrap().1.code_pointer;
+            let right = self.parse_to_expression_tree_shift();
+            left = Box::new(Expression::Operation2(
+                op,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
+        }
+    }
+
+    // & ^ | の順に優先度が低くなる (C と同じ)
+    fn parse_to_expression_tree_bit_and(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
+        let mut left = self.parse_to_expression_tree_compare();
+        while let Some((Token::Ampersand, token_info)) = self.iter.peek() {
+            let code_pointer = token_info.code_pointer;
             self.iter.next();
-            let right = self.parse_to_expression_tree_plus();
-            left = Box::new(Expression::Operation2(op, left, right));
+            let right = self.parse_to_expression_tree_compare();
+            left = Box::new(Expression::Operation2(
+                Operator2::BitAnd,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
+        }
+        left
+    }
+
+    fn parse_to_expression_tree_bit_xor(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
+        let mut left = self.parse_to_expression_tree_bit_and();
+        while let Some((Token::Caret, token_info)) = self.iter.peek() {
+            let code_pointer = token_info.code_pointer;
+            self.iter.next();
+            let right = self.parse_to_expression_tree_bit_and();
+            left = Box::new(Expression::Operation2(
+                Operator2::BitXor,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
+        }
+        left
+    }
+
+    fn parse_to_expression_tree_bit_or(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
+        let mut left = self.parse_to_expression_tree_bit_xor();
+        while let Some((Token::VerticalBar, token_info)) = self.iter.peek() {
+            let code_pointer = token_info.code_pointer;
+            self.iter.next();
+            let right = self.parse_to_expression_tree_bit_xor();
+            left = Box::new(Expression::Operation2(
+                Operator2::BitOr,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
+        }
+        left
+    }
+
+    fn parse_to_expression_tree_logical_and(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
+        let mut left = self.parse_to_expression_tree_bit_or();
+        while let Some((Token::DoubleAmpersand, token_info)) = self.iter.peek() {
+            let code_pointer = token_info.code_pointer;
+            self.iter.next();
+            let right = self.parse_to_expression_tree_bit_or();
+            left = Box::new(Expression::Operation2(
+                Operator2::LogicalAnd,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
+        }
+        left
+    }
+
+    fn parse_to_expression_tree_logical_or(&mut self) -> Box<Expression> {
+        let begin = self.iter.begin();
+        let mut left = self.parse_to_expression_tree_logical_and();
+        while let Some((Token::DoubleVerticalBar, token_info)) = self.iter.peek() {
+            let code_pointer = token_info.code_pointer;
+            self.iter.next();
+            let right = self.parse_to_expression_tree_logical_and();
+            left = Box::new(Expression::Operation2(
+                Operator2::LogicalOr,
+                left,
+                right,
+                code_pointer,
+                self.iter.span_from(begin),
+            ));
         }
+        left
     }
 
     fn parse_to_expression_tree_assign(&mut self) -> Box<Expression> {
-        let left = self.parse_to_expression_tree_compare();
-        let op = if let Some(token) = self.iter.peek() {
-            match token {
-                (Token::SingleEqual, _) => Operator2::Assign,
-                _ => return left,
-            }
-        } else {
-            return left;
+        let begin = self.iter.begin();
+        let left = self.parse_to_expression_tree_logical_or();
+        let op = match self.iter.peek() {
+            Some((Token::SingleEqual, _)) => Operator2::Assign,
+            Some((Token::PlusEqual, _)) => Operator2::PlusAssign,
+            Some((Token::MinusEqual, _)) => Operator2::MinusAssign,
+            Some((Token::AsteriskEqual, _)) => Operator2::MultiplyAssign,
+            Some((Token::SlashEqual, _)) => Operator2::DivideAssign,
+            Some((Token::PercentEqual, _)) => Operator2::ModuloAssign,
+            _ => return left,
         };
-        self.iter.next();
+        let code_pointer = self.iter.next().unwrap().1.code_pointer;
         let right = self.parse_to_expression_tree_assign();
-        Box::new(Expression::Operation2(op, left, right))
+        Box::new(Expression::Operation2(
+            op,
+            left,
+            right,
+            code_pointer,
+            self.iter.span_from(begin),
+        ))
     }
 
     fn parse_to_expression_tree_while(&mut self) -> Box<Expression> {
@@ -291,72 +625,72 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
             Some((Token::Keyword(Keyword::While), _)) => (),
             _ => return self.parse_to_expression_tree_assign(),
         }
+        let begin = self.iter.begin();
         self.iter.next();
 
-        if let Err(e) = match_expect_token!(self, self.iter.next(), Token::Colon) {
+        if let Err(e) = match_expect_token!(self, self.iter.peek(), Token::Colon) {
             return Box::new(Expression::Invalid(e));
         }
+        self.iter.next();
         let cond = self.parse_to_expression_tree_root();
-        if let Err(e) = match_expect_token!(self, self.iter.next(), Token::BraceL) {
-            return Box::new(Expression::Invalid(e));
-        }
-        let (stat, mut stat_err) = parse_to_statements(self.iter);
-        if !stat_err.is_empty() {
-            self.code_parse_error.append(&mut stat_err);
-        }
+        let stat = self.parse_to_expression_tree_block();
+        Box::new(Expression::While(cond, stat, self.iter.span_from(begin)))
+    }
+
+    // if, while のブロック `{ ... }` の中を読む
+    fn parse_to_expression_tree_block(&mut self) -> Vec<Statement> {
+        self.parse_to_expression_tree_block_begin();
+        let (stats, mut stats_err) = parse_to_statements(self.iter);
+        self.code_parse_error.append(&mut stats_err);
         match_expect_token_unused!(self, self.iter.next(), Token::BraceR);
-        Box::new(Expression::While(cond, stat))
+        stats
     }
 
+    // if: c1 { } elsif: c2 { } else: { }
+    // `else: if:` は `elsif:` と同じく、1 つの If の分岐として並べる
     fn parse_to_expression_tree_if(&mut self) -> Box<Expression> {
         match self.iter.peek() {
             Some((Token::Keyword(Keyword::If), _)) => (),
             _ => return self.parse_to_expression_tree_while(),
         };
+        let begin = self.iter.begin();
         self.iter.next();
 
-        if let Err(e) = match_expect_token!(self, self.iter.next(), Token::Colon) {
-            return Box::new(Expression::Invalid(e));
-        }
-        let cond = self.parse_to_expression_tree_root();
-        if let Err(e) = match_expect_token!(self, self.iter.next(), Token::BraceL) {
+        let mut branches = vec![];
+        let stats_else = loop {
+            if let Err(e) = match_expect_token!(self, self.iter.peek(), Token::Colon) {
+                return Box::new(Expression::Invalid(e));
+            }
+            self.iter.next();
+            let cond = self.parse_to_expression_tree_root();
             // NOTE: statements ではなく expression が来ても許容、でいいかもね？
-            return Box::new(Expression::Invalid(e));
-        }
-
-        let (stats_true, mut stats_err) = parse_to_statements(self.iter);
-        if !stats_err.is_empty() {
-            self.code_parse_error.append(&mut stats_err);
-        }
-
-        match_expect_token_unused!(self, self.iter.next(), Token::BraceR);
+            let stats = self.parse_to_expression_tree_block();
+            branches.push((cond, stats));
 
-        let stats_false = match self.iter.peek() {
-            Some((Token::Keyword(Keyword::Else), _)) => {
-                self.iter.next();
-                match_expect_token_unused!(self, self.iter.next(), Token::Colon);
-
-                match self.iter.peek() {
-                    Some((Token::Keyword(Keyword::If), _)) => {
-                        // else: if: cond {}
-                        // TODO: elsif を実装したほうが便利？
-                        // TODO: allow single expression ???
-                        vec![Statement::Expression(self.parse_to_expression_tree_if())]
+            match self.iter.peek() {
+                Some((Token::Keyword(Keyword::Elsif), _)) => {
+                    self.iter.next();
+                }
+                Some((Token::Keyword(Keyword::Else), _)) => {
+                    self.iter.next();
+                    if let Err(e) = match_expect_token!(self, self.iter.peek(), Token::Colon) {
+                        return Box::new(Expression::Invalid(e));
                     }
-                    _ => {
-                        let (stats, mut stats_err) = parse_to_statements(self.iter);
-                        if !stats_err.is_empty() {
-                            self.code_parse_error.append(&mut stats_err);
-                        }
-                        stats
+                    self.iter.next();
+                    if let Some((Token::Keyword(Keyword::If), _)) = self.iter.peek() {
+                        self.iter.next();
+                        continue;
                     }
+                    break self.parse_to_expression_tree_block();
                 }
-            }
-            _ => {
-                vec![]
+                _ => break vec![],
             }
         };
-        Box::new(Expression::If(cond, stats_true, stats_false))
+        Box::new(Expression::If(
+            branches,
+            stats_else,
+            self.iter.span_from(begin),
+        ))
     }
 
     fn parse_to_expression_tree_root(&mut self) -> Box<Expression> {
@@ -366,7 +700,7 @@ impl<'b: 'a, 'a> ExpressionBuilder<'b, 'a> {
 }
 
 pub(super) fn parse_to_expression_tree_root(
-    iter: &mut iter::Peekable<std::slice::Iter<PrettyToken>>,
+    iter: &mut TokenIter,
 ) -> (Box<Expression>, Vec<CodeParseErrorInternal>) {
     ExpressionBuilder::parse(iter)
 }
@@ -1,5 +1,9 @@
-use crate::base::CodeParseErrorInternal;
-use crate::token_parser::PrettyToken;
+use std::{iter, slice};
+
+use crate::base::{CodeParseErrorInternal, Span};
+use crate::code_parse_error;
+use crate::diagnostic::code;
+use crate::token_parser::{PrettyToken, Token};
 
 pub(crate) use self::expression::Expression;
 pub(crate) use self::expression::Operator1;
@@ -10,16 +14,96 @@ pub(crate) use self::statement::Statement;
 mod expression;
 mod statement;
 
+// トークン列を読み進めつつ、最後に読んだトークンの終端を覚えておく。
+// 構文木のノードの範囲 (Span) を求めるのに使う
+struct TokenIter<'a> {
+    iter: iter::Peekable<slice::Iter<'a, PrettyToken>>,
+    end: usize,
+}
+
+impl<'a> Iterator for TokenIter<'a> {
+    type Item = &'a PrettyToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.iter.next();
+        if let Some((_, info)) = token {
+            self.end = info.end;
+        }
+        token
+    }
+}
+
+impl<'a> TokenIter<'a> {
+    fn new(tokens: &'a [PrettyToken]) -> Self {
+        TokenIter {
+            iter: tokens.iter().peekable(),
+            end: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&'a PrettyToken> {
+        self.iter.peek().copied()
+    }
+
+    // 次のトークンの位置。無ければ最後に読んだトークンの終端
+    fn begin(&mut self) -> usize {
+        match self.iter.peek() {
+            Some((_, info)) => info.code_pointer,
+            None => self.end,
+        }
+    }
+
+    // 文の終わり (`;` か `}`) までに pred を満たすトークンがあるか。読み進めない
+    fn lookahead_in_statement(&self, pred: fn(&Token) -> bool) -> bool {
+        self.iter
+            .clone()
+            .map(|(token, _)| token)
+            .take_while(|token| !matches!(token, Token::Semicolon | Token::BraceR))
+            .any(pred)
+    }
+
+    // begin から最後に読んだトークンの終端まで
+    fn span_from(&self, begin: usize) -> Span {
+        Span::new(begin, self.end.max(begin))
+    }
+}
+
 // convert token sequence to tree structure.
 
 pub fn parse_to_tree(
-    tokens: &Vec<PrettyToken>,
+    tokens: &[PrettyToken],
 ) -> Result<Vec<Statement>, Vec<CodeParseErrorInternal>> {
-    let mut iter = tokens.iter().peekable();
-    let (st, err) = parse_to_statements(&mut iter);
+    let mut iter = TokenIter::new(tokens);
+    let mut st = vec![];
+    let mut err = vec![];
+    loop {
+        let (mut s, mut e) = parse_to_statements(&mut iter);
+        st.append(&mut s);
+        err.append(&mut e);
+        // 対応する `{` が無い `}` で止まった。読み飛ばして続ける
+        match iter.next() {
+            Some((_, info)) => err.push(
+                code_parse_error!(info.code_pointer, "unexpected token: `}`".to_owned())
+                    .with_code(code::UNEXPECTED_TOKEN)
+                    .with_span(info.span()),
+            ),
+            None => break,
+        }
+    }
+    // 入力の終わりのエラーは閉じていない括弧の数だけ出るので、1 つにまとめる
+    let mut end_reported = false;
+    err.retain(|e| {
+        let is_end = e.code == Some(code::UNEXPECTED_END);
+        let keep = !(is_end && end_reported);
+        end_reported |= is_end;
+        keep
+    });
     if err.is_empty() {
         Ok(st)
     } else {
         Err(err)
     }
 }
+
+#[cfg(test)]
+mod test;
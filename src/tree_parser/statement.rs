@@ -1,12 +1,18 @@
-use std::iter;
-
 use crate::{
-    base::CodeParseErrorInternal,
+    base::{CodeParseErrorInternal, Span, Value},
     code_parse_error,
-    token_parser::{Keyword, PrettyToken, Token, TokenInfo},
+    diagnostic::code,
+    token_parser::{Keyword, Token, TokenInfo},
 };
 
-use super::expression::*;
+use super::{expression::*, TokenIter};
+
+// 配列の大きさの上限 (spec.md 5 配列)
+// インタプリタは関数の呼び出しごとに、その関数の配列をすべて vec! で確保する。
+// 2^20 要素なら i64 で 8 MiB (bigint では値ごとにさらに確保) なので、
+// 再帰の中で宣言しても数段でメモリを使い切らない大きさにしている。
+// コンパイル結果のヒープ (vm.rs の BTreeMap) も 1 要素あたり数十バイトを使うので、同じ上限で足りる。
+const MAX_ARRAY_SIZE: i64 = 1 << 20;
 
 //
 
@@ -37,6 +43,16 @@ macro_rules! match_expect_token {
             None => Err($self.add_end_error("unexpected end of input".to_owned())),
         }
     };
+    ($self: expr, $v: expr, $pat: pat, $info: ident => $res: expr) => {
+        match $v {
+            Some(($pat, $info)) => Ok($res),
+            Some((_, token_info)) => Err($self.add_parse_error(
+                token_info,
+                format!("unexpected token: expected {}", stringify!($pat)),
+            )),
+            None => Err($self.add_end_error("unexpected end of input".to_owned())),
+        }
+    };
     ($self: expr, $v: expr, $pat: pat => $res: expr) => {
         match $v {
             Some(($pat, _)) => Ok($res),
@@ -49,6 +65,18 @@ macro_rules! match_expect_token {
     };
 }
 
+// 期待するトークンの時だけ読み進める match_expect_token
+// 期待しないトークンは消費しないので、文の立て直し (synchronize) で読み飛ばす
+macro_rules! match_expect_token_peek {
+    ($self: expr, $($t: tt)*) => {{
+        let res = match_expect_token!($self, $self.iter.peek(), $($t)*);
+        if res.is_ok() {
+            $self.iter.next();
+        }
+        res
+    }};
+}
+
 // TODO: unused_must_use is experimental... remove this
 macro_rules! match_expect_token_unused {
     ($self: expr, $v: expr, $pat: pat) => {
@@ -62,28 +90,28 @@ macro_rules! match_expect_token_unused {
 
 //
 
-#[derive(Clone)] // TODO: REMOVE
+// NOTE: 末尾の Span は文の範囲 (宣言なら識別子から、それ以外は先頭のトークンから)
+#[derive(Clone, Debug)] // TODO: REMOVE Clone
 pub enum Statement {
-    VariableDeclaration(String, Box<Expression>),
-    FunctionDeclaration(String, Vec<String>, Vec<Statement>),
-    Continue,
-    Break,
-    Return(Box<Expression>),
-    Expression(Box<Expression>),
+    VariableDeclaration(String, Box<Expression>, Span),
+    ArrayDeclaration(String, i64, Span), // i64: the number of elements
+    FunctionDeclaration(String, Vec<(String, Span)>, Vec<Statement>, Span), // (String, Span): 引数の名前とその位置
+    Continue(Span),
+    Break(Span),
+    Return(Box<Expression>, Span),
+    Expression(Box<Expression>, Span),
     Invalid(usize), // See, Expression::Invalid
 }
 
 //
 
 struct StatementBuilder<'b: 'a, 'a> {
-    iter: &'a mut iter::Peekable<std::slice::Iter<'b, PrettyToken>>,
+    iter: &'a mut TokenIter<'b>,
     code_parse_error: Vec<CodeParseErrorInternal>,
 }
 
 impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
-    fn parse(
-        iter: &'a mut iter::Peekable<std::slice::Iter<'b, PrettyToken>>,
-    ) -> (Vec<Statement>, Vec<CodeParseErrorInternal>) {
+    fn parse(iter: &'a mut TokenIter<'b>) -> (Vec<Statement>, Vec<CodeParseErrorInternal>) {
         let mut b = Self {
             iter,
             code_parse_error: vec![],
@@ -93,53 +121,182 @@ impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
     }
 
     fn add_parse_error(&mut self, token_info: &TokenInfo, msg: String) -> usize {
+        self.add_parse_error_with_code(token_info, code::UNEXPECTED_TOKEN, msg)
+    }
+    fn add_parse_error_with_code(
+        &mut self,
+        token_info: &TokenInfo,
+        code: u16,
+        msg: String,
+    ) -> usize {
         let i = self.code_parse_error.len();
-        self.code_parse_error
-            .push(code_parse_error!(token_info.code_pointer, msg.to_string()));
+        self.code_parse_error.push(
+            code_parse_error!(token_info.code_pointer, msg)
+                .with_code(code)
+                .with_span(token_info.span()),
+        );
         i
     }
     fn add_end_error(&mut self, msg: String) -> usize {
         let i = self.code_parse_error.len();
-        self.code_parse_error
-            .push(code_parse_error!(msg.to_string()));
+        let end = self.iter.end;
+        self.code_parse_error.push(
+            code_parse_error!(msg)
+                .with_code(code::UNEXPECTED_END)
+                .with_span(Span::new(end, end)),
+        );
         i
     }
 
+    // エラーから立て直す。次の `;` (読む) か、ブロックを閉じる `}` (読まない) まで読み飛ばす
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some((token, _)) = self.iter.peek() {
+            match token {
+                Token::Semicolon if depth == 0 => {
+                    self.iter.next();
+                    return;
+                }
+                Token::BraceR if depth == 0 => return,
+                Token::BraceL => depth += 1,
+                Token::BraceR => depth -= 1,
+                _ => (),
+            }
+            self.iter.next();
+        }
+    }
+
+    // 文末の `;` を読む。無ければ立て直す
+    // error_count: 文を読み始めた時のエラーの数。文の途中で既に報告していれば重ねて報告しない
+    fn parse_to_statements_end(&mut self, error_count: usize) {
+        if let Some((Token::Semicolon, _)) = self.iter.peek() {
+            self.iter.next();
+            return;
+        }
+        if self.code_parse_error.len() == error_count {
+            let _ = match_expect_token!(self, self.iter.peek(), Token::Semicolon);
+        }
+        self.synchronize();
+    }
+
     fn parse_to_statements_block(&mut self) -> Vec<Statement> {
         match_expect_token_unused!(self, self.iter.next(), Token::BraceL);
         let ss = self.parse_to_statements();
         match_expect_token_unused!(self, self.iter.next(), Token::BraceR);
-        return ss;
+        ss
     }
 
-    fn parse_to_statements_let(&mut self) -> Statement {
-        if let Err(_) = match_expect_token!(self, self.iter.next(), Token::Keyword(Keyword::Let)) {
+    // let: x = 1, y, a[10];
+    // 宣言子毎に VariableDeclaration (ArrayDeclaration) を返す
+    fn parse_to_statements_let(&mut self) -> Vec<Statement> {
+        if match_expect_token!(self, self.iter.next(), Token::Keyword(Keyword::Let)).is_err() {
             panic!("internal error");
         }
-        match_expect_token_unused!(self, self.iter.next(), Token::Colon);
-        let id = match match_expect_token!(self, self.iter.next(), Token::Identifier(id) => id) {
-            Ok(x) => x,
-            Err(e) => {
-                return Statement::Invalid(e);
+        let error_count = self.code_parse_error.len();
+        let _ = match_expect_token_peek!(self, Token::Colon);
+        let mut statements = Vec::<Statement>::new();
+        loop {
+            let (id, id_info) = match match_expect_token_peek!(
+                self,
+                Token::Identifier(id),
+                info => (id, info)
+            ) {
+                Ok(x) => x,
+                Err(e) => {
+                    statements.push(Statement::Invalid(e));
+                    self.synchronize();
+                    return statements;
+                }
+            };
+            let code_pointer = id_info.code_pointer;
+            if let Some((Token::BracketL, _)) = self.iter.peek() {
+                self.iter.next();
+                let size = match match_expect_token_peek!(
+                    self,
+                    Token::Number(n),
+                    info => (n.to_i64(), info)
+                ) {
+                    Ok((Some(n), info)) if n <= 0 => {
+                        self.add_parse_error_with_code(
+                            info,
+                            code::INVALID_ARRAY_DECLARATION,
+                            "array size must be positive".to_owned(),
+                        );
+                        n
+                    }
+                    Ok((Some(n), _)) if n <= MAX_ARRAY_SIZE => n,
+                    Ok((_, info)) => {
+                        self.add_parse_error_with_code(
+                            info,
+                            code::INVALID_ARRAY_DECLARATION,
+                            format!("array size is too large (max: {})", MAX_ARRAY_SIZE),
+                        );
+                        0
+                    }
+                    Err(e) => {
+                        statements.push(Statement::Invalid(e));
+                        self.synchronize();
+                        return statements;
+                    }
+                };
+                let _ = match_expect_token_peek!(self, Token::BracketR);
+                if let Some((Token::SingleEqual, info)) = self.iter.peek() {
+                    self.add_parse_error_with_code(
+                        info,
+                        code::INVALID_ARRAY_DECLARATION,
+                        "array can't have an initializer".to_owned(),
+                    );
+                    self.iter.next();
+                    let (_, mut errs) = parse_to_expression_tree_root(self.iter);
+                    self.code_parse_error.append(&mut errs);
+                }
+                statements.push(Statement::ArrayDeclaration(
+                    id.clone(),
+                    size,
+                    self.iter.span_from(code_pointer),
+                ));
+            } else {
+                let init = if let Some((Token::SingleEqual, _)) = self.iter.peek() {
+                    self.iter.next();
+                    let (expr, mut errs) = parse_to_expression_tree_root(self.iter);
+                    self.code_parse_error.append(&mut errs);
+                    expr
+                } else {
+                    Box::new(Expression::Factor(Value::from(0), id_info.span()))
+                };
+                statements.push(Statement::VariableDeclaration(
+                    id.clone(),
+                    init,
+                    self.iter.span_from(code_pointer),
+                ));
             }
-        };
-        match_expect_token_unused!(self, self.iter.next(), Token::Semicolon);
-        return Statement::VariableDeclaration(id.clone(), Box::new(Expression::Factor(0)));
+            if let Some((Token::Comma, _)) = self.iter.peek() {
+                self.iter.next();
+                continue;
+            }
+            break;
+        }
+        self.parse_to_statements_end(error_count);
+        statements
     }
 
     fn parse_to_statements_func(&mut self) -> Statement {
-        if let Err(_) = match_expect_token!(self, self.iter.next(), Token::Keyword(Keyword::Func)) {
+        if match_expect_token!(self, self.iter.next(), Token::Keyword(Keyword::Func)).is_err() {
             panic!("internal error");
         }
-        match_expect_token_unused!(self, self.iter.next(), Token::Colon);
-        let id = match match_expect_token!(self, self.iter.next(), Token::Identifier(id) => id) {
+        let _ = match_expect_token_peek!(self, Token::Colon);
+        let (id, code_pointer) = match match_expect_token_peek!(
+            self,
+            Token::Identifier(id),
+            info => (id, info.code_pointer)
+        ) {
             Ok(x) => x,
-            Err(e) => {
-                return Statement::Invalid(e);
-            }
+            Err(e) => return self.parse_to_statements_func_invalid(e),
         };
-        match_expect_token_unused!(self, self.iter.next(), Token::ParenthesisL);
-        let mut args = Vec::<String>::new();
+        if let Err(e) = match_expect_token_peek!(self, Token::ParenthesisL) {
+            return self.parse_to_statements_func_invalid(e);
+        }
+        let mut args = Vec::<(String, Span)>::new();
         enum State {
             L,
             Var,
@@ -147,14 +304,19 @@ impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
         }
         let mut state = State::L;
         loop {
-            match self.iter.next() {
+            // NOTE: 想定外のトークンは読み飛ばしの処理に任せるので、peek しておく
+            match self.iter.peek() {
                 Some((Token::Identifier(name), token_info)) => {
                     if let State::Var = state {
                         // note: 引数のparseに失敗するなら続行するべきではないと思う
                         self.add_parse_error(token_info, "expected ','".to_owned());
                     }
-                    args.push(name.clone());
+                    args.push((
+                        name.clone(),
+                        Span::new(token_info.code_pointer, token_info.end),
+                    ));
                     state = State::Var;
+                    self.iter.next();
                 }
                 Some((Token::Comma, token_info)) => {
                     if let State::Var = state {
@@ -162,41 +324,71 @@ impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
                     } else {
                         self.add_parse_error(token_info, "unexpected ','".to_owned());
                     }
+                    self.iter.next();
                 }
                 Some((Token::ParenthesisR, token_info)) => {
                     if let State::Comma = state {
+                        // 末尾の ',' は報告だけして、宣言の続きを読む
                         self.add_parse_error(token_info, "unexpected ','".to_owned());
-                    } else {
-                        break;
                     }
+                    self.iter.next();
+                    break;
                 }
                 Some((_, token_info)) => {
-                    self.add_parse_error(token_info, "unexpected token".to_owned());
-                    break;
+                    let e = self.add_parse_error(token_info, "unexpected token".to_owned());
+                    return self.parse_to_statements_func_invalid(e);
                 }
                 None => {
-                    self.add_end_error("unexpected end of input".to_owned());
-                    break;
+                    return Statement::Invalid(
+                        self.add_end_error("unexpected end of input".to_owned()),
+                    );
                 }
             }
         }
         if let Err(e) = match_expect_token!(self, self.iter.peek(), Token::BraceL) {
-            self.iter.next(); // NOTE: nextが安全だが不親切とは思う
-            return Statement::Invalid(e);
+            return self.parse_to_statements_func_invalid(e);
         }
-        return Statement::FunctionDeclaration(id.clone(), args, self.parse_to_statements_block());
+        let block = self.parse_to_statements_block();
+        Statement::FunctionDeclaration(id.clone(), args, block, self.iter.span_from(code_pointer))
     }
 
-    fn parse_to_statements_return(&mut self) -> Statement {
-        if let Err(_) = match_expect_token!(self, self.iter.next(), Token::Keyword(Keyword::Return))
-        {
-            panic!("internal error");
+    // 宣言の残りを読み飛ばす。本体のブロックが見つかれば、その中のエラーは報告する
+    fn parse_to_statements_func_invalid(&mut self, e: usize) -> Statement {
+        while let Some((token, _)) = self.iter.peek() {
+            match token {
+                Token::BraceL => {
+                    self.parse_to_statements_block();
+                    break;
+                }
+                Token::BraceR => break,
+                Token::Semicolon => {
+                    self.iter.next();
+                    break;
+                }
+                _ => {
+                    self.iter.next();
+                }
+            }
         }
-        match_expect_token_unused!(self, self.iter.next(), Token::Colon);
+        Statement::Invalid(e)
+    }
+
+    fn parse_to_statements_return(&mut self) -> Statement {
+        let code_pointer = match match_expect_token!(
+            self,
+            self.iter.next(),
+            Token::Keyword(Keyword::Return),
+            info => info.code_pointer
+        ) {
+            Ok(x) => x,
+            Err(_) => panic!("internal error"),
+        };
+        let error_count = self.code_parse_error.len();
+        let _ = match_expect_token_peek!(self, Token::Colon);
         let (expr, mut errs) = parse_to_expression_tree_root(self.iter);
         self.code_parse_error.append(&mut errs);
-        match_expect_token_unused!(self, self.iter.next(), Token::Semicolon);
-        return Statement::Return(expr);
+        self.parse_to_statements_end(error_count);
+        Statement::Return(expr, self.iter.span_from(code_pointer))
     }
 
     fn parse_to_statements(&mut self) -> Vec<Statement> {
@@ -204,7 +396,7 @@ impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
         while let Some(token) = self.iter.peek() {
             match token {
                 (Token::Keyword(Keyword::Let), _) => {
-                    statements.push(self.parse_to_statements_let());
+                    statements.append(&mut self.parse_to_statements_let());
                     continue;
                 }
                 (Token::Keyword(Keyword::Func), _) => {
@@ -215,16 +407,20 @@ impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
                     statements.push(self.parse_to_statements_return());
                     continue;
                 }
-                (Token::Keyword(Keyword::Break), _) => {
+                (Token::Keyword(Keyword::Break), token_info) => {
                     self.iter.next();
-                    statements.push(Statement::Break);
-                    match_expect_token_unused!(self, self.iter.next(), Token::Semicolon);
+                    self.parse_to_statements_end(self.code_parse_error.len());
+                    statements.push(Statement::Break(
+                        self.iter.span_from(token_info.code_pointer),
+                    ));
                     continue;
                 }
-                (Token::Keyword(Keyword::Continue), _) => {
+                (Token::Keyword(Keyword::Continue), token_info) => {
                     self.iter.next();
-                    statements.push(Statement::Continue);
-                    match_expect_token_unused!(self, self.iter.next(), Token::Semicolon);
+                    self.parse_to_statements_end(self.code_parse_error.len());
+                    statements.push(Statement::Continue(
+                        self.iter.span_from(token_info.code_pointer),
+                    ));
                     continue;
                 }
                 (Token::BraceR, _) => {
@@ -233,18 +429,23 @@ impl<'b: 'a, 'a> StatementBuilder<'b, 'a> {
                 }
                 _ => {}
             }
+            let code_pointer = token.1.code_pointer;
+            let error_count = self.code_parse_error.len();
             let (expr, mut errs) = parse_to_expression_tree_root(self.iter);
             self.code_parse_error.append(&mut errs);
-            statements.push(Statement::Expression(expr));
-            match_expect_token_unused!(self, self.iter.next(), Token::Semicolon);
+            self.parse_to_statements_end(error_count);
+            statements.push(Statement::Expression(
+                expr,
+                self.iter.span_from(code_pointer),
+            ));
         }
-        return statements;
+        statements
         // panic!("syntax error: terminal");
     }
 }
 
 pub(super) fn parse_to_statements(
-    iter: &mut iter::Peekable<std::slice::Iter<PrettyToken>>,
+    iter: &mut TokenIter,
 ) -> (Vec<Statement>, Vec<CodeParseErrorInternal>) {
     StatementBuilder::parse(iter)
 }
@@ -0,0 +1,210 @@
+use crate::{
+    base::{CodeParseErrorInternal, Span},
+    diagnostic::code,
+    token_parser::parse_to_tokens,
+    tree_parser::{parse_to_tree, Expression, Operator2, Statement},
+};
+
+fn parse(code: &str) -> Vec<Statement> {
+    let tokens = parse_to_tokens(code).ok().unwrap();
+    parse_to_tree(&tokens).ok().unwrap()
+}
+
+fn parse_errors(code: &str) -> Vec<CodeParseErrorInternal> {
+    let tokens = parse_to_tokens(code).ok().unwrap();
+    match parse_to_tree(&tokens) {
+        Ok(_) => vec![],
+        Err(e) => e,
+    }
+}
+
+// 1 つの誤りにつきエラーが 1 つだけ出ること
+macro_rules! test_ng_parse {
+    ($name: ident, $code: expr, $($ptr: expr),*) => {
+        #[test]
+        fn $name() {
+            let errors = parse_errors($code);
+            let pointers: Vec<_> = errors.iter().map(|e| e.code_pointer).collect();
+            assert_eq!(pointers, vec![$(Some($ptr)),*]);
+        }
+    };
+}
+
+#[test]
+fn test_declaration_span() {
+    let tree = parse("let: a = 1, b[2]; func: f(x) { return: -x * (a + 2); }");
+    assert_matches!(
+        &tree[0],
+        Statement::VariableDeclaration(_, init, span)
+            if *span == Span::new(5, 10)
+                && matches!(**init, Expression::Factor(_, s) if s == Span::new(9, 10))
+    );
+    assert_matches!(&tree[1], Statement::ArrayDeclaration(_, 2, span) if *span == Span::new(12, 16));
+    let body = match &tree[2] {
+        Statement::FunctionDeclaration(_, _, body, span) => {
+            assert_eq!(*span, Span::new(24, 54));
+            body
+        }
+        _ => panic!("expected a function"),
+    };
+    let expr = match &body[0] {
+        Statement::Return(expr, span) => {
+            assert_eq!(*span, Span::new(31, 52));
+            expr
+        }
+        _ => panic!("expected return"),
+    };
+    match &**expr {
+        Expression::Operation2(Operator2::Multiply, l, r, 42, span) => {
+            assert_eq!(*span, Span::new(39, 51));
+            assert_matches!(**l, Expression::Operation1(_, _, 39, s) if s == Span::new(39, 41));
+            assert_matches!(**r, Expression::Operation2(_, _, _, 47, s) if s == Span::new(45, 50));
+        }
+        _ => panic!("expected `*`"),
+    }
+}
+
+#[test]
+fn test_expression_span() {
+    let tree = parse("func: f() { while: g(1) { a[3] = 0; }; }");
+    let body = match &tree[0] {
+        Statement::FunctionDeclaration(_, _, body, _) => body,
+        _ => panic!("expected a function"),
+    };
+    let (cond, stats) = match &body[0] {
+        Statement::Expression(expr, span) => {
+            assert_eq!(*span, Span::new(12, 38));
+            match &**expr {
+                Expression::While(cond, stats, span) => {
+                    assert_eq!(*span, Span::new(12, 37));
+                    (cond, stats)
+                }
+                _ => panic!("expected while"),
+            }
+        }
+        _ => panic!("expected an expression"),
+    };
+    assert_matches!(**cond, Expression::Function(_, _, s) if s == Span::new(19, 23));
+    match &stats[0] {
+        Statement::Expression(expr, span) => {
+            assert_eq!(*span, Span::new(26, 35));
+            match &**expr {
+                Expression::Operation2(Operator2::Assign, l, _, 31, span) => {
+                    assert_eq!(*span, Span::new(26, 34));
+                    assert_matches!(**l, Expression::ArrayElement(_, _, s) if s == Span::new(26, 30));
+                }
+                _ => panic!("expected `=`"),
+            }
+        }
+        _ => panic!("expected an expression"),
+    }
+}
+
+test_ng_parse!(
+    test_ng_recover_in_parenthesis,
+    "func: f() { x = (1 + ; y = 1; }",
+    21
+);
+test_ng_parse!(
+    test_ng_recover_independent_statements,
+    "func: f() { let: 1; x = 2 3; return: 0; }",
+    17,
+    26
+);
+test_ng_parse!(test_ng_recover_stray_brace, "func: f() { } } let: x;", 14);
+test_ng_parse!(
+    test_ng_recover_unclosed_call,
+    "func: f() { g(1; h(2); }",
+    15
+);
+test_ng_parse!(
+    test_ng_recover_missing_brace,
+    "func: f() { while: 1 x = 1; }; y = ; }",
+    21,
+    35
+);
+test_ng_parse!(
+    test_ng_recover_function_header,
+    "func: f(1) { a = ; } func: g() {}",
+    8,
+    17
+);
+test_ng_parse!(
+    test_ng_recover_trailing_comma_in_arguments,
+    "func: f(a,) { return: a; } func: g(,b) { return: b; }",
+    10,
+    35
+);
+test_ng_parse!(
+    test_ng_recover_unclosed_arguments,
+    "func: f(a { b = ; } func: g() {}",
+    10,
+    16
+);
+test_ng_parse!(test_ng_recover_array_declaration, "let: a[; let: b;", 7);
+
+#[test]
+fn test_ng_array_size() {
+    let errors = parse_errors("let: a[0], b[1048577], c[99999999999999]; let: d[1048576];");
+    let codes: Vec<_> = errors.iter().map(|e| (e.code, e.code_pointer)).collect();
+    assert_eq!(
+        codes,
+        vec![
+            (Some(code::INVALID_ARRAY_DECLARATION), Some(7)),
+            (Some(code::INVALID_ARRAY_DECLARATION), Some(13)),
+            (Some(code::INVALID_ARRAY_DECLARATION), Some(25)),
+        ]
+    );
+}
+
+#[test]
+fn test_ng_recover_end_of_input() {
+    let errors = parse_errors("func: f() { while: 1 { if: 1 {");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, Some(code::UNEXPECTED_END));
+    assert_eq!(errors[0].span, Some(Span::new(30, 30)));
+}
+
+#[test]
+fn test_ok_recover_keeps_valid_statements() {
+    // エラーの後の文も読めている
+    let code = "func: f() { x = ; } func: g() { return: 1; }";
+    assert_eq!(parse_errors(code).len(), 1);
+    let tokens = parse_to_tokens(code).ok().unwrap();
+    let (tree, _) = super::parse_to_statements(&mut super::TokenIter::new(&tokens));
+    assert_matches!(
+        &tree[1],
+        Statement::FunctionDeclaration(name, _, body, _) if name == "g" && body.len() == 1
+    );
+}
+
+#[test]
+fn test_if_chain() {
+    let tree = parse(
+        "func: f(x) { if: x { } elsif: x - 1 { a; } else: if: x - 2 { a; b; } else: { c; }; }",
+    );
+    let body = match &tree[0] {
+        Statement::FunctionDeclaration(_, _, body, _) => body,
+        _ => panic!("expected a function"),
+    };
+    match &body[0] {
+        Statement::Expression(expr, _) => match &**expr {
+            Expression::If(branches, stats_else, span) => {
+                // `else: if:` も入れ子にせず、分岐として並ぶ
+                let sizes: Vec<_> = branches.iter().map(|(_, stats)| stats.len()).collect();
+                assert_eq!(sizes, vec![0, 1, 2]);
+                assert_eq!(stats_else.len(), 1);
+                assert_eq!(*span, Span::new(13, 81));
+            }
+            _ => panic!("expected if"),
+        },
+        _ => panic!("expected an expression"),
+    }
+}
+
+// else: の後はブロックが必要
+test_ng_parse!(
+    test_ng_else_without_brace,
+    "func: f(x) { if: x { } else: x = 1; }; g(); }",
+    29
+);
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use crate::{base::CodeParseErrorInternal, code_parse_error};
+
+// Whitespace の命令セット
+// ラベルは名前で保持し、エンコード時に番号を振る。
+
+pub type Label = String;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    // stack manipulation
+    Push(i64),
+    Dup,
+    Copy(i64),
+    Swap,
+    Discard,
+    Slide(i64),
+    // arithmetic
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    // heap access
+    Store,
+    Retrieve,
+    // flow control
+    Mark(Label),
+    Call(Label),
+    Jump(Label),
+    JumpZero(Label),
+    JumpNegative(Label),
+    Return,
+    Exit,
+    // I/O
+    OutChar,
+    OutNum,
+    ReadChar,
+    ReadNum,
+}
+
+const S: char = ' ';
+const T: char = '\t';
+const L: char = '\n';
+
+fn encode_number(code: &mut String, value: i64) {
+    code.push(if value < 0 { T } else { S });
+    // NOTE: 0 を空のビット列で表すと一部の処理系が受け付けないので、最低 1 桁は出力する
+    for b in format!("{:b}", value.unsigned_abs()).chars() {
+        code.push(if b == '1' { T } else { S });
+    }
+    code.push(L);
+}
+
+struct LabelMap {
+    map: BTreeMap<Label, usize>,
+}
+
+impl LabelMap {
+    fn encode(&mut self, code: &mut String, label: &str) {
+        let n = self.map.len();
+        let id = *self.map.entry(label.to_owned()).or_insert(n);
+        for b in format!("{:b}", id).chars() {
+            code.push(if b == '1' { T } else { S });
+        }
+        code.push(L);
+    }
+}
+
+// convert instructions to whitespace code.
+pub fn encode(instructions: &[Instruction]) -> String {
+    let mut code = String::new();
+    let mut labels = LabelMap {
+        map: BTreeMap::new(),
+    };
+    for inst in instructions {
+        let cmd: &[char] = match inst {
+            Instruction::Push(_) => &[S, S],
+            Instruction::Dup => &[S, L, S],
+            Instruction::Copy(_) => &[S, T, S],
+            Instruction::Swap => &[S, L, T],
+            Instruction::Discard => &[S, L, L],
+            Instruction::Slide(_) => &[S, T, L],
+            Instruction::Add => &[T, S, S, S],
+            Instruction::Sub => &[T, S, S, T],
+            Instruction::Mul => &[T, S, S, L],
+            Instruction::Div => &[T, S, T, S],
+            Instruction::Mod => &[T, S, T, T],
+            Instruction::Store => &[T, T, S],
+            Instruction::Retrieve => &[T, T, T],
+            Instruction::Mark(_) => &[L, S, S],
+            Instruction::Call(_) => &[L, S, T],
+            Instruction::Jump(_) => &[L, S, L],
+            Instruction::JumpZero(_) => &[L, T, S],
+            Instruction::JumpNegative(_) => &[L, T, T],
+            Instruction::Return => &[L, T, L],
+            Instruction::Exit => &[L, L, L],
+            Instruction::OutChar => &[T, L, S, S],
+            Instruction::OutNum => &[T, L, S, T],
+            Instruction::ReadChar => &[T, L, T, S],
+            Instruction::ReadNum => &[T, L, T, T],
+        };
+        code.extend(cmd);
+        match inst {
+            Instruction::Push(n) | Instruction::Copy(n) | Instruction::Slide(n) => {
+                encode_number(&mut code, *n)
+            }
+            Instruction::Mark(label)
+            | Instruction::Call(label)
+            | Instruction::Jump(label)
+            | Instruction::JumpZero(label)
+            | Instruction::JumpNegative(label) => labels.encode(&mut code, label),
+            _ => (),
+        }
+    }
+    code
+}
+
+// whitespace code -> instructions
+
+struct Decoder<I: Iterator<Item = (usize, char)>> {
+    iter: I,
+}
+
+impl<I: Iterator<Item = (usize, char)>> Decoder<I> {
+    // NOTE: S, T, L 以外の文字はコメントとして無視する
+    fn next(&mut self) -> Option<(usize, char)> {
+        self.iter.find(|(_, c)| *c == S || *c == T || *c == L)
+    }
+
+    fn next_char(&mut self) -> Result<char, CodeParseErrorInternal> {
+        match self.next() {
+            Some((_, c)) => Ok(c),
+            None => Err(code_parse_error!("unexpected end of input".to_owned())),
+        }
+    }
+
+    fn parse_bits(&mut self) -> Result<Vec<bool>, CodeParseErrorInternal> {
+        let mut bits = vec![];
+        loop {
+            match self.next_char()? {
+                S => bits.push(false),
+                T => bits.push(true),
+                _ => return Ok(bits),
+            }
+        }
+    }
+
+    fn parse_number(&mut self, code_pointer: usize) -> Result<i64, CodeParseErrorInternal> {
+        let negative = match self.next_char()? {
+            S => false,
+            T => true,
+            _ => return Ok(0),
+        };
+        let mut value = 0i64;
+        for b in self.parse_bits()? {
+            let d = if negative { -(b as i64) } else { b as i64 };
+            value = match value.checked_mul(2).and_then(|x| x.checked_add(d)) {
+                Some(x) => x,
+                None => {
+                    return Err(code_parse_error!(
+                        code_pointer,
+                        "number is too large".to_owned()
+                    ))
+                }
+            };
+        }
+        Ok(value)
+    }
+
+    fn parse_label(&mut self) -> Result<Label, CodeParseErrorInternal> {
+        Ok(self
+            .parse_bits()?
+            .iter()
+            .map(|b| if *b { '1' } else { '0' })
+            .collect())
+    }
+
+    fn parse_instruction(
+        &mut self,
+        code_pointer: usize,
+        imp: char,
+    ) -> Result<Instruction, CodeParseErrorInternal> {
+        let inst = match (imp, self.next_char()?) {
+            (S, S) => Instruction::Push(self.parse_number(code_pointer)?),
+            (S, T) => match self.next_char()? {
+                S => Instruction::Copy(self.parse_number(code_pointer)?),
+                L => Instruction::Slide(self.parse_number(code_pointer)?),
+                _ => {
+                    return Err(code_parse_error!(
+                        code_pointer,
+                        "invalid instruction".to_owned()
+                    ))
+                }
+            },
+            (S, L) => match self.next_char()? {
+                S => Instruction::Dup,
+                T => Instruction::Swap,
+                _ => Instruction::Discard,
+            },
+            (T, S) => match (self.next_char()?, self.next_char()?) {
+                (S, S) => Instruction::Add,
+                (S, T) => Instruction::Sub,
+                (S, L) => Instruction::Mul,
+                (T, S) => Instruction::Div,
+                (T, T) => Instruction::Mod,
+                _ => {
+                    return Err(code_parse_error!(
+                        code_pointer,
+                        "invalid instruction".to_owned()
+                    ))
+                }
+            },
+            (T, T) => match self.next_char()? {
+                S => Instruction::Store,
+                T => Instruction::Retrieve,
+                _ => {
+                    return Err(code_parse_error!(
+                        code_pointer,
+                        "invalid instruction".to_owned()
+                    ))
+                }
+            },
+            (T, L) => match (self.next_char()?, self.next_char()?) {
+                (S, S) => Instruction::OutChar,
+                (S, T) => Instruction::OutNum,
+                (T, S) => Instruction::ReadChar,
+                (T, T) => Instruction::ReadNum,
+                _ => {
+                    return Err(code_parse_error!(
+                        code_pointer,
+                        "invalid instruction".to_owned()
+                    ))
+                }
+            },
+            (L, c2) => match (c2, self.next_char()?) {
+                (S, S) => Instruction::Mark(self.parse_label()?),
+                (S, T) => Instruction::Call(self.parse_label()?),
+                (S, L) => Instruction::Jump(self.parse_label()?),
+                (T, S) => Instruction::JumpZero(self.parse_label()?),
+                (T, T) => Instruction::JumpNegative(self.parse_label()?),
+                (T, L) => Instruction::Return,
+                (L, L) => Instruction::Exit,
+                _ => {
+                    return Err(code_parse_error!(
+                        code_pointer,
+                        "invalid instruction".to_owned()
+                    ))
+                }
+            },
+            _ => {
+                return Err(code_parse_error!(
+                    code_pointer,
+                    "invalid instruction".to_owned()
+                ))
+            }
+        };
+        Ok(inst)
+    }
+
+    fn parse(&mut self) -> Result<Vec<Instruction>, CodeParseErrorInternal> {
+        let mut instructions = vec![];
+        while let Some((code_pointer, imp)) = self.next() {
+            instructions.push(self.parse_instruction(code_pointer, imp)?);
+        }
+        Ok(instructions)
+    }
+}
+
+pub fn parse(code: &str) -> Result<Vec<Instruction>, Vec<CodeParseErrorInternal>> {
+    let mut decoder = Decoder {
+        iter: code.chars().enumerate(),
+    };
+    decoder.parse().map_err(|e| vec![e])
+}
+
+pub use self::vm::VirtualMachine;
+
+mod vm;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,170 @@
+use std::io;
+
+use crate::whitespace::{encode, parse, Instruction, VirtualMachine};
+
+fn visualize(code: &str) -> String {
+    code.chars()
+        .map(|c| match c {
+            ' ' => 'S',
+            '\t' => 'T',
+            '\n' => 'L',
+            _ => panic!("invalid char"),
+        })
+        .collect()
+}
+
+#[test]
+fn test_encode_number() {
+    assert_eq!(visualize(&encode(&[Instruction::Push(0)])), "SSSSL");
+    assert_eq!(visualize(&encode(&[Instruction::Push(5)])), "SSSTSTL");
+    assert_eq!(visualize(&encode(&[Instruction::Push(-2)])), "SSTTSL");
+}
+
+#[test]
+fn test_encode_label() {
+    let code = encode(&[
+        Instruction::Mark("main".to_owned()),
+        Instruction::Call("f".to_owned()),
+        Instruction::Jump("main".to_owned()),
+        Instruction::Exit,
+    ]);
+    assert_eq!(visualize(&code), "LSSSLLSTTLLSLSLLLL");
+}
+
+#[test]
+fn test_encode_arithmetic() {
+    let code = encode(&[
+        Instruction::Add,
+        Instruction::Sub,
+        Instruction::Mul,
+        Instruction::Div,
+        Instruction::Mod,
+    ]);
+    assert_eq!(visualize(&code), "TSSSTSSTTSSLTSTSTSTT");
+}
+
+#[test]
+fn test_parse_roundtrip() {
+    let instructions = vec![
+        Instruction::Push(-12),
+        Instruction::Copy(1),
+        Instruction::Slide(2),
+        Instruction::Mark("0".to_owned()),
+        Instruction::JumpNegative("0".to_owned()),
+        Instruction::ReadNum,
+        Instruction::Return,
+    ];
+    assert_eq!(parse(&encode(&instructions)).ok().unwrap(), instructions);
+}
+
+#[test]
+fn test_parse_ignores_comments() {
+    let res = parse("push(1)   \t\nend\n\n\n").ok().unwrap();
+    assert_eq!(res, vec![Instruction::Push(1), Instruction::Exit]);
+}
+
+#[test]
+fn test_parse_invalid() {
+    assert_matches!(parse("\t\t\n"), Err(e) if e[0].code_pointer == Some(0));
+    assert_matches!(parse("  \t"), Err(e) if e[0].code_pointer.is_none());
+}
+
+fn run(instructions: &[Instruction], input: &str) -> Result<String, String> {
+    let mut vm = VirtualMachine::new(instructions).map_err(|e| e.message)?;
+    let mut output = Vec::<u8>::new();
+    vm.run(&mut io::Cursor::new(input), &mut output)
+        .map_err(|e| e.message)?;
+    Ok(String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_vm_arithmetic() {
+    let res = run(
+        &[
+            Instruction::Push(-7),
+            Instruction::Push(2),
+            Instruction::Div,
+            Instruction::OutNum,
+            Instruction::Push(-7),
+            Instruction::Push(2),
+            Instruction::Mod,
+            Instruction::OutNum,
+            Instruction::Exit,
+        ],
+        "",
+    );
+    assert_eq!(res, Ok("-41".to_owned()));
+}
+
+#[test]
+fn test_vm_io() {
+    let res = run(
+        &[
+            Instruction::Push(0),
+            Instruction::ReadNum,
+            Instruction::Push(1),
+            Instruction::ReadChar,
+            Instruction::Push(1),
+            Instruction::Retrieve,
+            Instruction::OutChar,
+            Instruction::Push(0),
+            Instruction::Retrieve,
+            Instruction::OutNum,
+            Instruction::Exit,
+        ],
+        "42\nあ",
+    );
+    assert_eq!(res, Ok("あ42".to_owned()));
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn test_vm_bigint_input() {
+    let res = run(
+        &[
+            Instruction::Push(0),
+            Instruction::ReadNum,
+            Instruction::Push(0),
+            Instruction::Retrieve,
+            Instruction::OutNum,
+            Instruction::Exit,
+        ],
+        "123456789012345678901234567890\n",
+    );
+    assert_eq!(res, Ok("123456789012345678901234567890".to_owned()));
+}
+
+#[test]
+fn test_vm_call() {
+    let res = run(
+        &[
+            Instruction::Call("f".to_owned()),
+            Instruction::OutNum,
+            Instruction::Exit,
+            Instruction::Mark("f".to_owned()),
+            Instruction::Push(3),
+            Instruction::Return,
+        ],
+        "",
+    );
+    assert_eq!(res, Ok("3".to_owned()));
+}
+
+#[test]
+fn test_vm_error() {
+    assert_eq!(
+        run(
+            &[Instruction::Push(1), Instruction::Push(0), Instruction::Div],
+            ""
+        ),
+        Err("division by zero".to_owned())
+    );
+    assert_eq!(
+        run(&[Instruction::Jump("x".to_owned())], ""),
+        Err("undefined label: x".to_owned())
+    );
+    assert_eq!(
+        run(&[Instruction::Push(1)], ""),
+        Err("unexpected end of program".to_owned())
+    );
+}
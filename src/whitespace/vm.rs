@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt,
+    io::{BufRead, Write},
+};
+
+use crate::base::{read_char, read_num, Value};
+
+use super::Instruction;
+
+pub struct VmError {
+    pub pc: usize,
+    pub message: String,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pc: {})", self.message, self.pc)
+    }
+}
+
+impl fmt::Debug for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+pub struct VirtualMachine<'a> {
+    instructions: &'a [Instruction],
+    labels: BTreeMap<&'a str, usize>,
+    pub stack: Vec<Value>,
+    pub heap: BTreeMap<i64, Value>,
+    call_stack: Vec<usize>,
+}
+
+impl<'a> VirtualMachine<'a> {
+    pub fn new(instructions: &'a [Instruction]) -> Result<Self, VmError> {
+        let mut labels = BTreeMap::new();
+        for (pc, inst) in instructions.iter().enumerate() {
+            if let Instruction::Mark(label) = inst {
+                if labels.insert(label.as_str(), pc).is_some() {
+                    return Err(VmError {
+                        pc,
+                        message: format!("duplicated label: {}", label),
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            instructions,
+            labels,
+            stack: vec![],
+            heap: BTreeMap::new(),
+            call_stack: vec![],
+        })
+    }
+
+    fn pop(&mut self) -> Result<Value, String> {
+        self.stack.pop().ok_or_else(|| "stack underflow".to_owned())
+    }
+
+    fn peek(&self, n: i64) -> Result<Value, String> {
+        let len = self.stack.len() as i64;
+        if n < 0 || n >= len {
+            return Err("stack underflow".to_owned());
+        }
+        Ok(self.stack[(len - 1 - n) as usize].clone())
+    }
+
+    // NOTE: ヒープのアドレスは i64 に収まるものに限る
+    fn pop_address(&mut self) -> Result<i64, String> {
+        let address = self.pop()?;
+        address
+            .to_i64()
+            .ok_or_else(|| format!("invalid address: {}", address))
+    }
+
+    fn label(&self, label: &str) -> Result<usize, String> {
+        match self.labels.get(label) {
+            Some(pc) => Ok(*pc),
+            None => Err(format!("undefined label: {}", label)),
+        }
+    }
+
+    fn arithmetic(
+        &mut self,
+        f: fn(&Value, &Value) -> Option<Value>,
+        zero_check: bool,
+    ) -> Result<(), String> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        if zero_check && b.is_zero() {
+            return Err("division by zero".to_owned());
+        }
+        match f(&a, &b) {
+            Some(x) => self.stack.push(x),
+            None => return Err("arithmetic overflow".to_owned()),
+        }
+        Ok(())
+    }
+
+    // 次に実行する命令の位置を返す。終了するなら None
+    fn step(
+        &mut self,
+        pc: usize,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<Option<usize>, String> {
+        let inst = match self.instructions.get(pc) {
+            Some(x) => x,
+            None => return Err("unexpected end of program".to_owned()),
+        };
+        match inst {
+            Instruction::Push(n) => self.stack.push(Value::from(*n)),
+            Instruction::Dup => self.stack.push(self.peek(0)?),
+            Instruction::Copy(n) => self.stack.push(self.peek(*n)?),
+            Instruction::Swap => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.stack.push(b);
+                self.stack.push(a);
+            }
+            Instruction::Discard => {
+                self.pop()?;
+            }
+            Instruction::Slide(n) => {
+                let top = self.pop()?;
+                if *n < 0 || *n as usize > self.stack.len() {
+                    return Err("stack underflow".to_owned());
+                }
+                self.stack.truncate(self.stack.len() - *n as usize);
+                self.stack.push(top);
+            }
+            Instruction::Add => self.arithmetic(Value::checked_add, false)?,
+            Instruction::Sub => self.arithmetic(Value::checked_sub, false)?,
+            Instruction::Mul => self.arithmetic(Value::checked_mul, false)?,
+            Instruction::Div => self.arithmetic(Value::checked_div_floor, true)?,
+            Instruction::Mod => self.arithmetic(Value::checked_mod_floor, true)?,
+            Instruction::Store => {
+                let value = self.pop()?;
+                let address = self.pop_address()?;
+                self.heap.insert(address, value);
+            }
+            Instruction::Retrieve => {
+                let address = self.pop_address()?;
+                // NOTE: 未初期化のアドレスは 0 とみなす
+                let value = match self.heap.get(&address) {
+                    Some(x) => x.clone(),
+                    None => Value::from(0),
+                };
+                self.stack.push(value);
+            }
+            Instruction::Mark(_) => (),
+            Instruction::Call(label) => {
+                self.call_stack.push(pc + 1);
+                return Ok(Some(self.label(label)?));
+            }
+            Instruction::Jump(label) => return Ok(Some(self.label(label)?)),
+            Instruction::JumpZero(label) => {
+                if self.pop()?.is_zero() {
+                    return Ok(Some(self.label(label)?));
+                }
+            }
+            Instruction::JumpNegative(label) => {
+                if self.pop()?.is_negative() {
+                    return Ok(Some(self.label(label)?));
+                }
+            }
+            Instruction::Return => match self.call_stack.pop() {
+                Some(x) => return Ok(Some(x)),
+                None => return Err("call stack underflow".to_owned()),
+            },
+            Instruction::Exit => return Ok(None),
+            Instruction::OutChar => {
+                let c = self.pop()?;
+                match c
+                    .to_i64()
+                    .and_then(|c| u32::try_from(c).ok())
+                    .and_then(std::char::from_u32)
+                {
+                    Some(ch) => write!(output, "{}", ch).map_err(|e| e.to_string())?,
+                    None => return Err(format!("invalid character: {}", c)),
+                }
+            }
+            Instruction::OutNum => {
+                let n = self.pop()?;
+                write!(output, "{}", n).map_err(|e| e.to_string())?;
+            }
+            Instruction::ReadChar => {
+                let address = self.pop_address()?;
+                output.flush().map_err(|e| e.to_string())?;
+                let c = read_char(input)?;
+                self.heap.insert(address, Value::from(c));
+            }
+            Instruction::ReadNum => {
+                let address = self.pop_address()?;
+                output.flush().map_err(|e| e.to_string())?;
+                let n = read_num(input)?;
+                self.heap.insert(address, n);
+            }
+        }
+        Ok(Some(pc + 1))
+    }
+
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> Result<(), VmError> {
+        let mut pc = 0;
+        loop {
+            match self.step(pc, input, output) {
+                Ok(Some(next)) => pc = next,
+                Ok(None) => break,
+                Err(message) => return Err(VmError { pc, message }),
+            }
+        }
+        output.flush().map_err(|e| VmError {
+            pc,
+            message: e.to_string(),
+        })
+    }
+}
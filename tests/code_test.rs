@@ -1,16 +1,15 @@
 use std::{fmt::Result, fs, io};
 
-use nospace20::{interpret_func_testing, parse_to_tokens, parse_to_tree, syntactic_analyze};
+use nospace20::{
+    assemble, compile_to_assembly, compile_to_whitespace, interpret_func_testing, parse_to_tokens,
+    parse_to_tree, run_whitespace_testing, syntactic_analyze, Value,
+};
 
 fn test_ok_coding_base(test_name: &str) -> Result {
     let path_base = "resources/test/".to_owned() + test_name;
     let ns_cnt = fs::read_to_string(path_base.to_owned() + ".ns")
         .expect("Something went wrong reading the file");
 
-    let t = parse_to_tokens(&ns_cnt).ok().unwrap();
-    let s = parse_to_tree(&t).ok().unwrap();
-    let a = syntactic_analyze(&s);
-    let trace = interpret_func_testing(&a, "main");
     let check_json: serde_json::Value = serde_json::from_reader(io::BufReader::new(
         fs::File::open(path_base.to_owned() + ".check.json")
             .ok()
@@ -18,12 +17,39 @@ fn test_ok_coding_base(test_name: &str) -> Result {
     ))
     .ok()
     .unwrap();
+    // optional: the text given to __read_int, __read_char
+    let input = check_json
+        .get("input")
+        .map_or("", |e| e.as_str().unwrap())
+        .to_owned();
+
+    let t = parse_to_tokens(&ns_cnt).ok().unwrap();
+    let s = parse_to_tree(&t).ok().unwrap();
+    let a = syntactic_analyze(&s).ok().unwrap();
+    let (trace, result, output) = interpret_func_testing(&a, "main", &input);
+
+    // the compiled code must behave the same as the interpreter
+    let (compiled_trace, compiled_result, compiled_output) =
+        run_whitespace_testing(&compile_to_whitespace(&a), &input);
+    assert_eq!(trace, compiled_trace, "compiled trace mismatch");
+    assert_eq!(output, compiled_output, "compiled output mismatch");
+    assert_eq!(
+        Some(result.unwrap_or_else(|| Value::from(0))),
+        compiled_result,
+        "compiled result mismatch"
+    );
+    assert_eq!(
+        assemble(&compile_to_assembly(&a)).ok(),
+        Some(compile_to_whitespace(&a)),
+        "assembly mismatch"
+    );
+
     let expected_trace = check_json
         .get("trace")
         .unwrap()
         .as_array()
         .unwrap()
-        .into_iter()
+        .iter()
         .map(|e| e.as_i64().unwrap());
     for (i, expected) in expected_trace.enumerate() {
         let key = i as i64;
@@ -33,6 +59,13 @@ fn test_ok_coding_base(test_name: &str) -> Result {
             panic!("idx:{} trace doesn't exist", key);
         }
     }
+    if let Some(expected_output) = check_json.get("output") {
+        assert_eq!(
+            expected_output.as_str().unwrap(),
+            String::from_utf8(output).unwrap(),
+            "output mismatch"
+        );
+    }
     Ok(())
 }
 
@@ -50,3 +83,19 @@ test_ok_coding!(test_ok_coding_c000, "c000");
 test_ok_coding!(test_ok_coding_c001, "c001");
 test_ok_coding!(test_ok_coding_c002, "c002");
 test_ok_coding!(test_ok_coding_c003, "c003");
+test_ok_coding!(test_ok_coding_c004, "c004");
+test_ok_coding!(test_ok_coding_c005, "c005");
+test_ok_coding!(test_ok_coding_c006, "c006");
+test_ok_coding!(test_ok_coding_c007, "c007");
+test_ok_coding!(test_ok_coding_c008, "c008");
+test_ok_coding!(test_ok_coding_c009, "c009");
+test_ok_coding!(test_ok_coding_c010, "c010");
+test_ok_coding!(test_ok_coding_c011, "c011");
+test_ok_coding!(test_ok_coding_c012, "c012");
+test_ok_coding!(test_ok_coding_c013, "c013");
+test_ok_coding!(test_ok_coding_c014, "c014");
+#[cfg(feature = "bigint")]
+test_ok_coding!(test_ok_coding_c015, "c015");
+test_ok_coding!(test_ok_coding_c016, "c016");
+test_ok_coding!(test_ok_coding_c017, "c017");
+test_ok_coding!(test_ok_coding_c018, "c018");